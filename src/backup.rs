@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use crate::{
+    errors::DotManResult,
+    state::{self, Backup, State},
+    utils,
+};
+
+pub fn backups_dir() -> DotManResult<String> {
+    let base = utils::xdg_dir("XDG_DATA_HOME", "~/.local/share")?;
+
+    Ok(Path::new(&base)
+        .join("dotman")
+        .join("backups")
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Moves `path` into the backups directory and records it in the state file,
+/// returns where it was moved to
+pub fn backup(state: &mut State, package: &str, path: &str) -> DotManResult<String> {
+    let created_at = state::now();
+    let dir = Path::new(&backups_dir()?).join(format!("{package}-{created_at}"));
+
+    // `restore --force` backs up a package twice within the same second
+    let mut destination = dir.clone();
+    let mut n = 1;
+    while destination.exists() {
+        destination = dir.with_file_name(format!("{package}-{created_at}-{n}"));
+        n += 1;
+    }
+    let destination = destination.to_string_lossy().to_string();
+
+    utils::move_dir(Path::new(path), Path::new(&destination))?;
+
+    state
+        .backups
+        .entry(package.to_owned())
+        .or_default()
+        .push(Backup {
+            path: destination.clone(),
+            original_path: path.to_owned(),
+            created_at,
+        });
+    state.save()?;
+
+    Ok(destination)
+}
+
+pub fn latest(state: &State, package: &str) -> Option<Backup> {
+    state.backups.get(package).and_then(|b| b.last()).cloned()
+}
+
+/// Moves `backup` back to where it came from and forgets about it
+pub fn restore(state: &mut State, package: &str, backup: &Backup) -> DotManResult<()> {
+    utils::move_dir(Path::new(&backup.path), Path::new(&backup.original_path))?;
+
+    if let Some(backups) = state.backups.get_mut(package) {
+        backups.retain(|b| b.path != backup.path);
+
+        if backups.is_empty() {
+            state.backups.remove(package);
+        }
+    }
+    state.save()?;
+
+    Ok(())
+}
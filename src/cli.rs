@@ -1,5 +1,7 @@
 use clap::{command, Parser, Subcommand, ValueEnum};
 
+use crate::config::LocalChanges;
+
 /// Manage dotfiles easially
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -7,6 +9,10 @@ use clap::{command, Parser, Subcommand, ValueEnum};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Show everything that is known about errors
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
 }
 
 #[derive(Subcommand)]
@@ -20,18 +26,98 @@ pub enum Commands {
         #[arg(short = 'y', long)]
         yes: bool,
 
-        /// Force install, this will override existing configurations
+        /// Force install, existing configurations will be backed up and replaced
         #[arg(long)]
         force: bool,
 
         /// Don't run .dotman.lua functions
         #[arg(long)]
         no_scripts: bool,
+
+        /// Check out the exact commits recorded in the lockfile
+        #[arg(long)]
+        locked: bool,
+
+        /// Print what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Number of packages to clone or fetch at the same time, overrides `jobs` in the config
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+
+        /// Clone over https instead of ssh, e.g. before any ssh keys are set up
+        #[arg(long)]
+        https: bool,
+
+        /// What to do with packages that have uncommitted changes or unpushed commits, overrides `local-changes` in the config
+        #[arg(long, value_enum)]
+        local_changes: Option<LocalChanges>,
+    },
+
+    /// Restore the latest backup made by `install --force`
+    Restore {
+        package: String,
+
+        /// Back up whatever is at the install path before restoring
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check a repository checkout for problems without installing anything
+    Validate {
+        /// Path to the repository checkout
+        #[arg(default_value = ".")]
+        path: String,
     },
 
     /// Search for packages and collections
     Search { query: String },
 
+    /// List packages and whether they are installed
+    List {
+        /// Only list installed packages
+        #[arg(long)]
+        installed: bool,
+
+        /// Only list packages that aren't installed
+        #[arg(long, conflicts_with_all = ["installed", "explicit", "deps", "outdated"])]
+        available: bool,
+
+        /// Only list packages that were installed explicitly
+        #[arg(long, conflicts_with = "deps")]
+        explicit: bool,
+
+        /// Only list packages that were installed as a dependency
+        #[arg(long)]
+        deps: bool,
+
+        /// Only list installed packages with upstream changes, this will fetch every installed package
+        #[arg(long)]
+        outdated: bool,
+    },
+
+    /// Show the chain of collections and dependencies that requires a package
+    Why {
+        package: String,
+
+        /// Packages or collections to start from, defaults to the explicitly installed packages
+        #[arg(long, num_args = 1..)]
+        from: Vec<String>,
+    },
+
+    /// List every package and collection that depends on a package
+    Rdeps { package: String },
+
+    /// Print the dependency graph of the repository
+    Graph {
+        /// Only include these packages and collections and everything they require
+        packages: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+    },
+
     /// View package information
     Inspect { package: String },
 
@@ -41,13 +127,33 @@ pub enum Commands {
         #[arg(short = 'y', long)]
         yes: bool,
 
-        /// Force install, this will override existing configurations
+        /// Force install, existing configurations will be backed up and replaced
         #[arg(long)]
         force: bool,
 
         /// Don't run .dotman-postinstall or .dotman-postupdate script
         #[arg(long)]
         no_scripts: bool,
+
+        /// Check out the exact commits recorded in the lockfile
+        #[arg(long)]
+        locked: bool,
+
+        /// Print what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Number of packages to clone or fetch at the same time, overrides `jobs` in the config
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+
+        /// Clone over https instead of ssh, e.g. before any ssh keys are set up
+        #[arg(long)]
+        https: bool,
+
+        /// What to do with packages that have uncommitted changes or unpushed commits, overrides `local-changes` in the config
+        #[arg(long, value_enum)]
+        local_changes: Option<LocalChanges>,
     },
 
     /// Updated selected packages, will install in not already installed
@@ -59,13 +165,52 @@ pub enum Commands {
         #[arg(short = 'y', long)]
         yes: bool,
 
-        /// Force install, this will override existing configurations
+        /// Force install, existing configurations will be backed up and replaced
         #[arg(long)]
         force: bool,
 
         /// Don't run .dotman-postinstall or .dotman-postupdate script
         #[arg(long)]
         no_scripts: bool,
+
+        /// Check out the exact commits recorded in the lockfile
+        #[arg(long)]
+        locked: bool,
+
+        /// Print what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Number of packages to clone or fetch at the same time, overrides `jobs` in the config
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+
+        /// Clone over https instead of ssh, e.g. before any ssh keys are set up
+        #[arg(long)]
+        https: bool,
+
+        /// What to do with packages that have uncommitted changes or unpushed commits, overrides `local-changes` in the config
+        #[arg(long, value_enum)]
+        local_changes: Option<LocalChanges>,
+    },
+
+    /// Uninstall selected packages
+    #[command(alias = "remove")]
+    Uninstall {
+        #[clap(required = true)]
+        packages: Vec<String>,
+
+        /// Skip confirmation
+        #[arg(short = 'y', long)]
+        yes: bool,
+
+        /// Also uninstall installed packages that depend on the selected packages
+        #[arg(long)]
+        cascade: bool,
+
+        /// Don't run .dotman.lua functions
+        #[arg(long)]
+        no_scripts: bool,
     },
 
     Script {
@@ -79,12 +224,40 @@ pub enum Commands {
 
 #[derive(ValueEnum, Debug, Clone)]
 pub enum Function {
+    PreInstall,
     PostInstall,
+    PreUpdate,
     PostUpdate,
+    PreUninstall,
+    PostUninstall,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
 }
 
 pub struct InstallUpdateArgs<'a> {
     pub yes: &'a bool,
     pub force: &'a bool,
     pub no_scripts: &'a bool,
+    pub locked: &'a bool,
+    pub dry_run: &'a bool,
+    pub jobs: &'a Option<usize>,
+    pub local_changes: &'a Option<LocalChanges>,
+}
+
+pub struct UninstallArgs<'a> {
+    pub yes: &'a bool,
+    pub cascade: &'a bool,
+    pub no_scripts: &'a bool,
+}
+
+pub struct ListArgs<'a> {
+    pub installed: &'a bool,
+    pub available: &'a bool,
+    pub explicit: &'a bool,
+    pub deps: &'a bool,
+    pub outdated: &'a bool,
 }
@@ -0,0 +1,131 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::{cli::GraphFormat, errors::DotManResult, repo::Repository, required_packages};
+
+struct Edge {
+    from: String,
+    to: String,
+    membership: bool,
+}
+
+pub fn graph(repo: &Repository, packages: &[String], format: &GraphFormat) -> DotManResult<()> {
+    let mut nodes = if packages.is_empty() {
+        repo.packages
+            .iter()
+            .map(|p| p.name.clone())
+            .chain(repo.collections.iter().map(|c| c.name.clone()))
+            .collect::<BTreeSet<_>>()
+    } else {
+        reachable(repo, packages)?
+    };
+
+    let mut edges = vec![];
+    for node in nodes.iter().filter(|n| is_known(repo, n)) {
+        let membership = is_collection(repo, node);
+        for child in required_packages::children(node, repo)? {
+            edges.push(Edge {
+                from: node.clone(),
+                to: child,
+                membership,
+            });
+        }
+    }
+
+    // Unknown or source qualified dependencies still get a node of their own
+    nodes.extend(edges.iter().map(|e| e.to.clone()));
+
+    let output = match format {
+        GraphFormat::Dot => dot(repo, &nodes, &edges),
+        GraphFormat::Mermaid => mermaid(repo, &nodes, &edges),
+    };
+
+    println!("{output}");
+
+    Ok(())
+}
+
+fn reachable(repo: &Repository, packages: &[String]) -> DotManResult<BTreeSet<String>> {
+    let mut seen = HashSet::new();
+    let mut queue = packages.to_vec();
+
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        // Dependencies that aren't defined anywhere are drawn as leaves, only the
+        // requested packages have to exist
+        if !is_known(repo, &name) && !packages.contains(&name) {
+            continue;
+        }
+
+        queue.extend(required_packages::children(&name, repo)?);
+    }
+
+    Ok(seen.into_iter().collect())
+}
+
+fn is_known(repo: &Repository, name: &str) -> bool {
+    repo.try_get_package_from_string(name).is_some() || is_collection(repo, name)
+}
+
+fn is_collection(repo: &Repository, name: &str) -> bool {
+    repo.try_get_collection_from_string(name).is_some()
+}
+
+// Collections are drawn as rounded boxes and their membership edges are dashed
+fn dot(repo: &Repository, nodes: &BTreeSet<String>, edges: &[Edge]) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut lines = vec!["digraph dotman {".to_owned()];
+
+    for node in nodes {
+        if is_collection(repo, node) {
+            lines.push(format!("    {} [shape=box, style=rounded];", quote(node)));
+        } else {
+            lines.push(format!("    {};", quote(node)));
+        }
+    }
+
+    for edge in edges {
+        let style = if edge.membership {
+            " [style=dashed]"
+        } else {
+            ""
+        };
+
+        lines.push(format!(
+            "    {} -> {}{};",
+            quote(&edge.from),
+            quote(&edge.to),
+            style
+        ));
+    }
+
+    lines.push("}".to_owned());
+    lines.join("\n")
+}
+
+// Mermaid ids can't contain most punctuation, so nodes get generated ids and
+// the name as their label
+fn mermaid(repo: &Repository, nodes: &BTreeSet<String>, edges: &[Edge]) -> String {
+    let id = |name: &str| format!("n{}", nodes.iter().position(|n| n == name).unwrap_or(0));
+    let label = |name: &str| name.replace('"', "#quot;");
+
+    let mut lines = vec!["graph LR".to_owned()];
+
+    for node in nodes {
+        if is_collection(repo, node) {
+            lines.push(format!("    {}([\"{}\"])", id(node), label(node)));
+        } else {
+            lines.push(format!("    {}[\"{}\"]", id(node), label(node)));
+        }
+    }
+
+    for edge in edges {
+        let arrow = if edge.membership { "-.->" } else { "-->" };
+        lines.push(format!("    {} {} {}", id(&edge.from), arrow, id(&edge.to)));
+    }
+
+    lines.join("\n")
+}
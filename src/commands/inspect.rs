@@ -1,23 +1,18 @@
-use crate::{print, repo::Repository};
-use colored::Colorize;
+use crate::{
+    errors::{DotManResult, Error},
+    repo::Repository,
+};
 
-pub fn inspect(repo: &Repository, package: &str) {
-    for pkg in &repo.packages {
-        if pkg.name == package {
-            pkg.pprint();
-            return;
-        }
+pub fn inspect(repo: &Repository, package: &str) -> DotManResult<()> {
+    if let Some(pkg) = repo.try_get_package_from_string(package) {
+        pkg.pprint();
+        return Ok(());
     }
 
-    for col in &repo.collections {
-        if col.name == package {
-            col.pprint();
-            return;
-        }
+    if let Some(col) = repo.try_get_collection_from_string(package) {
+        col.pprint();
+        return Ok(());
     }
 
-    print::warning(&format!(
-        "No package or collection named '{}' was found",
-        package.bold()
-    ));
+    Err(Error::UnknownPackage(package.to_owned()))
 }
@@ -1,19 +1,24 @@
-use std::{fs, io};
+use std::{
+    collections::VecDeque, env, fs, io, process::Command, sync::Mutex, thread, time::Duration,
+};
 
 use colored::Colorize;
 
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 
 use crate::{
+    backup,
     cli::InstallUpdateArgs,
-    config::{Config, GitUpdateType},
+    config::{Config, GitUpdateType, LocalChanges},
     errors::{DotManResult, Error, GitError},
-    gitactions::GitWrapper,
-    package::Package,
+    gitactions::{Git, GitWrapper, Resolution},
+    lockfile::Lockfile,
+    package::{Package, Pin},
     print::{self, Printer},
     repo::Repository,
     required_packages,
     script::Script,
+    state::{self, InstallReason, InstalledPackage, State},
 };
 
 pub fn install_or_update(
@@ -24,9 +29,27 @@ pub fn install_or_update(
 ) -> DotManResult<()> {
     let deps_pb = ProgressBar::new_spinner();
     deps_pb.set_message("Loading dependencies...");
+    let explicit = required_packages::explicit_packages(packages, repo);
+    let collections = packages
+        .iter()
+        .filter_map(|name| repo.try_get_collection_from_string(name))
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
     let packages = required_packages::gather_required_packages(packages, &repo)?;
     deps_pb.finish_with_message("Loading dependencies... DONE");
 
+    let mut lockfile = if *args.locked {
+        Lockfile::load(conf)?
+    } else {
+        Lockfile::load_or_default(conf)?
+    };
+
+    if *args.locked {
+        for pkg in &packages {
+            lockfile.commit(&repo.package_id(&repo.get_package_from_string(pkg)))?;
+        }
+    }
+
     let packages_string = packages
         .iter()
         .map(|p| p.clone().italic().bold().to_string())
@@ -39,6 +62,10 @@ pub fn install_or_update(
         packages_string
     ));
 
+    if *args.dry_run {
+        return plan(conf, repo, &args, &packages, &lockfile);
+    }
+
     if !(*args.yes) {
         let mut confirm = inquire::Confirm::new("Do you want to procced?").with_default(true);
 
@@ -63,83 +90,206 @@ pub fn install_or_update(
         print::warning("Running with no confirmation...");
     }
 
+    let mut state = State::load()?;
+
     // TODO: Make the required parent directories
 
-    for pkg in packages {
-        let pkg = repo.get_package_from_string(&pkg);
+    let policy = args.local_changes.unwrap_or(conf.git.local_changes);
+
+    // Everything that can stop the install or needs the terminal happens before
+    // the network work starts
+    let mut tasks = vec![];
+    let mut backups = vec![];
+    for name in &packages {
+        let pkg = repo.get_package_from_string(name);
         let install_path = pkg.install_path()?;
-        let url = pkg.url();
+        let locked_commit = if *args.locked {
+            Some(lockfile.commit(&repo.package_id(&pkg))?.to_owned())
+        } else {
+            None
+        };
+        // Locked or pinned to a tag or commit, stays on a detached HEAD instead of following a branch
+        let detached = locked_commit.or(pkg.detached_ref());
 
         let pp = print::Printer::new(pkg.name.clone());
 
-        let script = match Script::load(&install_path, &pp) {
-            Ok(s) => Some(s),
-            Err(Error::MissingScript) => None,
-            Err(e) => return Err(e),
-        };
-
         pp.info(&format!(
             "Looking if '{}' already exists...",
             install_path.italic()
         ));
 
-        match GitWrapper::open(&url, &install_path) {
+        let existing = match GitWrapper::open(&pkg.url(), &install_path) {
             Err(Error::Git(GitError::NotARepository(_))) => {
                 if !(*args.force) {
-                    pp.fatal(&format!(
-                        "'{}' exists but isn't a dotman repo, exiting...",
-                        install_path.italic()
-                    ));
+                    return Err(Error::NotADotmanRepo(install_path));
+                }
 
-                    panic!();
+                backups.push((repo.package_id(&pkg), install_path));
+                None
+            }
+            Err(Error::IO(e)) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+            Ok(wrapper) => {
+                if let LocalChanges::Abort = policy {
+                    let remote = wrapper.get_remote_name()?;
+                    if let Some(changes) = local_changes(&wrapper, &remote)? {
+                        return Err(Error::LocalChanges(pkg.name, changes));
+                    }
                 }
 
-                pp.warning(&format!(
-                    "'{}' exists but isn't a dotman repo, removing...",
-                    install_path.italic()
-                ));
+                Some(wrapper)
+            }
+        };
 
-                fs::remove_dir_all(install_path.clone())?;
+        if existing.is_none() && pkg.repo.is_local() && pkg.clone_depth(conf).is_some() {
+            pp.warning("Local repositories can't be cloned shallow, cloning the whole history...");
+        }
+
+        tasks.push(Task {
+            pkg,
+            detached,
+            existing,
+        });
+    }
+
+    // Nothing can stop the install anymore
+    for (name, install_path) in backups {
+        let pp = print::Printer::new(name.clone());
+        pp.warning(&format!(
+            "'{}' exists but isn't a dotman repo, backing it up...",
+            install_path.italic()
+        ));
 
-                clone(&pp, &pkg, &args)?;
+        let destination = backup::backup(&mut state, &name, &install_path)?;
+        pp.info(&format!(
+            "Backed up to '{}', run `dotman restore {}` to put it back",
+            destination.italic(),
+            name
+        ));
+    }
+
+    let jobs = args.jobs.unwrap_or(conf.git.jobs);
+    let mut skipped = vec![];
+
+    let fetched = fetch_all(conf, tasks, jobs);
+
+    // Packages are only set up up to the first failed fetch, the fresh clones after it are
+    // removed so the next install sets them up instead of taking them for existing packages
+    if let Some(failed) = fetched.iter().position(|(_, result)| result.is_err()) {
+        for (task, result) in &fetched[failed + 1..] {
+            if let Ok(Fetched::Cloned(_)) = result {
+                let install_path = task.pkg.install_path()?;
+                print::Printer::new(task.pkg.name.clone()).warning(&format!(
+                    "Removing '{}', {} failed before it could be installed...",
+                    install_path.italic(),
+                    fetched[failed].0.pkg.name.bold()
+                ));
+                fs::remove_dir_all(&install_path)?;
             }
-            Err(Error::IO(e)) if e.kind() == io::ErrorKind::NotFound => {
-                clone(&pp, &pkg, &args)?;
+        }
+    }
+
+    // Scripts can depend on each other, so they still run one package at a time in dependency order
+    for (name, (task, fetched)) in packages.into_iter().zip(fetched) {
+        let Task { pkg, detached, .. } = task;
+        let install_path = pkg.install_path()?;
+        let url = pkg.url();
+
+        let pp = print::Printer::new(pkg.name.clone());
+
+        let wrapper = match fetched? {
+            Fetched::Cloned(wrapper) => {
+                installed(&pp, &pkg, &args, detached.as_deref(), &wrapper)?;
+                wrapper
             }
-            Err(e) => return Err(e),
-            Ok(wrapper) => {
+            Fetched::Updated {
+                mut wrapper,
+                remote,
+                branch,
+            } => {
+                let script = load_script(&install_path, &pp)?;
+
                 print::info(&format!("Updating {}!", pkg.name.bold().italic()));
                 pp.info(&format!(
                     "'{}' exists and is a dotman repo, updating instead...",
                     install_path.italic()
                 ));
 
+                // An empty branch name means HEAD is detached, e.g. by a previous --locked install
                 let current_branch = wrapper.current_branch_name()?;
-                if current_branch != "master" {
+
+                let mut stashed = false;
+                if let Some(changes) = local_changes(&wrapper, &remote)? {
+                    match policy {
+                        LocalChanges::Abort => return Err(Error::LocalChanges(pkg.name, changes)),
+                        LocalChanges::Skip => {
+                            pp.warning(&format!("Has {changes}, skipping..."));
+                            skipped.push(pkg.name);
+                            continue;
+                        }
+                        LocalChanges::Autostash => {
+                            if wrapper.is_dirty()? {
+                                pp.info("Stashing uncommitted changes...");
+                                wrapper.stash()?;
+                                stashed = true;
+                            }
+                        }
+                        LocalChanges::Reset => {
+                            pp.warning(&format!("Throwing away {changes}..."));
+                            if wrapper.unpushed_commits(&remote)? > 0 {
+                                wrapper.reset(&format!("{remote}/{current_branch}"))?;
+                            } else {
+                                wrapper.reset("HEAD")?;
+                            }
+                        }
+                    }
+                }
+
+                // `sparse-paths` might have changed since the package was cloned
+                wrapper.set_sparse_paths(&pkg.sparse_paths())?;
+
+                let switch_branch = detached.is_none() && current_branch != branch;
+                if switch_branch && current_branch.is_empty() {
+                    pp.info(&format!(
+                        "Currently on a detached HEAD. switching to '{}' branch...",
+                        branch.bold()
+                    ));
+                    wrapper.checkout_branch(&branch)?;
+                    pp.success(&format!("Switched to '{}' branch!", branch.bold()));
+                    on_branch_switch(&args, script.as_ref(), &current_branch, &branch)?;
+                } else if switch_branch {
                     pp.info(&format!(
                         "Currently on '{}' branch. switching to '{}' branch...",
                         current_branch.italic(),
-                        "master".bold()
+                        branch.bold()
+                    ));
+                    pp.warning(&format!(
+                        "Changes won't take effect until you switch back to {branch}!"
                     ));
-                    pp.warning("Changes won't take effect until you switch back to master!");
-                    wrapper.checkout_branch("master")?;
-                    pp.success(&format!("Switched to '{}' branch!", "master".bold()));
+                    wrapper.checkout_branch(&branch)?;
+                    pp.success(&format!("Switched to '{}' branch!", branch.bold()));
+                    on_branch_switch(&args, script.as_ref(), &current_branch, &branch)?;
                 }
 
-                let remote = wrapper.get_remote_name()?;
-                // FIXME: Handle merge confilcts somehow...
-                match conf.git.update_type {
-                    GitUpdateType::FetchRebase => {
-                        pp.info("Fetching and rebasing changes...");
-                        wrapper.fetch(&remote)?;
-                        wrapper.rebase()?;
-                        pp.success("Changes has been fetched and rebased!");
+                let updated = hook(
+                    &pp,
+                    &args,
+                    script.as_ref(),
+                    "pre_update",
+                    Script::run_preupdate,
+                )
+                .and_then(|_| update(conf, &pp, &wrapper, &remote, &branch, &detached, &args))
+                .and_then(|_| submodules(&pp, &pkg, &wrapper));
+                if let Err(e) = updated {
+                    // Leave the package on the branch and with the changes it was found with
+                    if switch_branch && !current_branch.is_empty() {
+                        wrapper.checkout_branch(&current_branch)?;
                     }
-                    GitUpdateType::Pull => {
-                        pp.info("Pulling changes...");
-                        wrapper.pull(&remote)?;
-                        pp.success("Changes has been pulled!");
+                    if stashed {
+                        wrapper.unstash()?;
                     }
+
+                    return Err(e);
                 }
 
                 if !(*args.no_scripts) {
@@ -147,7 +297,7 @@ pub fn install_or_update(
                         "Running `{}` script if it exists...",
                         ".dotman.lua:M.post_update".italic()
                     ));
-                    if let Some(script) = script {
+                    if let Some(script) = &script {
                         script.run_postupdate()?;
                     }
                 } else {
@@ -157,44 +307,407 @@ pub fn install_or_update(
                     ));
                 }
 
-                if current_branch != "master" {
+                if switch_branch && !current_branch.is_empty() {
                     pp.info(&format!(
                         "Switching back to '{}' branch...",
                         current_branch.italic(),
                     ));
                     wrapper.checkout_branch(&current_branch)?;
                     pp.success(&format!("Switched to '{}' branch!", current_branch.bold()));
+                    on_branch_switch(&args, script.as_ref(), &branch, &current_branch)?;
+                }
+
+                if stashed {
+                    pp.info("Reapplying stashed changes...");
+                    match wrapper.unstash() {
+                        Ok(_) => pp.success("Stashed changes have been reapplied!"),
+                        Err(e) => {
+                            e.print_warning();
+                            pp.warning(
+                                "Stashed changes are kept, resolve the conflicts and run `git stash drop`",
+                            );
+                        }
+                    }
                 }
 
                 print::success(&format!(
                     "{} has been successfully updated!",
                     pkg.name.bold().italic()
                 ));
+
+                wrapper
             }
+        };
+
+        let reason = if explicit.contains(&name) {
+            InstallReason::Explicit
+        } else {
+            InstallReason::Dependency
+        };
+
+        let commit = wrapper.head_commit()?;
+        if !(*args.locked) {
+            lockfile.lock(&repo.package_id(&pkg), &url, &commit);
         }
+
+        state.record(
+            &repo.package_id(&pkg),
+            InstalledPackage {
+                commit,
+                installed_at: state::now(),
+                updated_at: None,
+                install_path,
+                url,
+                repository: repo
+                    .source(&pkg.source)
+                    .map(|s| s.url())
+                    .unwrap_or_default(),
+                reason,
+            },
+        );
+        state.save()?;
+    }
+
+    if !collections.is_empty() {
+        state.collections.extend(collections);
+        state.save()?;
+    }
+
+    if !(*args.locked) {
+        lockfile.sources = repo.commits.clone();
+        lockfile.save(conf)?;
+    }
+
+    if !skipped.is_empty() {
+        print::warning(&format!(
+            "Skipped {} packages with local changes: {}",
+            skipped.len(),
+            skipped.join(", ")
+        ));
     }
 
     Ok(())
 }
 
-fn clone(pp: &Printer, pkg: &Package, args: &InstallUpdateArgs) -> DotManResult<()> {
-    let install_path = pkg.install_path()?;
-    let url = pkg.url();
+// Brings the checked out branch up to date, or checks out `detached`
+fn update(
+    conf: &Config,
+    pp: &Printer,
+    wrapper: &GitWrapper,
+    remote: &str,
+    branch: &str,
+    detached: &Option<String>,
+    args: &InstallUpdateArgs,
+) -> DotManResult<()> {
+    match (detached, &conf.git.update_type) {
+        (Some(target), _) => {
+            pp.info(&format!("Checking out '{}'...", target.italic()));
+            wrapper.checkout_detached(target)?;
+            pp.success(&format!("'{}' has been checked out!", target.bold()));
+        }
+        (None, GitUpdateType::FetchRebase) => {
+            pp.info("Rebasing onto the fetched changes...");
+            match wrapper.rebase(remote, branch, &Resolution::Abort) {
+                Err(Error::Git(GitError::Conflict(path, files))) if !(*args.yes) => {
+                    resolve_conflicts(conf, pp, wrapper, remote, branch, path, files)?
+                }
+                res => res?,
+            }
+            pp.success("Changes has been fetched and rebased!");
+        }
+        (None, GitUpdateType::Pull) => {
+            pp.info("Merging the fetched changes...");
+            match wrapper.merge(remote, branch, &Resolution::Abort) {
+                Err(Error::Git(GitError::Conflict(path, files))) if !(*args.yes) => {
+                    resolve_conflicts(conf, pp, wrapper, remote, branch, path, files)?
+                }
+                res => res?,
+            }
+            pp.success("Changes has been pulled!");
+        }
+    }
 
-    pp.info(&format!(
-        "Cloning {} from {} to {}... ",
-        pkg.name.italic().bold(),
-        url.italic(),
-        install_path.italic()
+    Ok(())
+}
+
+fn load_script(install_path: &str, pp: &Printer) -> DotManResult<Option<Script>> {
+    match Script::load(install_path, pp) {
+        Ok(s) => Ok(Some(s)),
+        Err(Error::MissingScript) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Runs a hook that doesn't have to be there, the post hooks warn when they're skipped
+fn hook(
+    pp: &Printer,
+    args: &InstallUpdateArgs,
+    script: Option<&Script>,
+    name: &str,
+    run: fn(&Script) -> DotManResult<()>,
+) -> DotManResult<()> {
+    if *args.no_scripts {
+        return Ok(());
+    }
+
+    if let Some(script) = script {
+        pp.info(&format!(
+            "Running `{}` function if it exists...",
+            format!(".dotman.lua:M.{name}").italic()
+        ));
+        run(script)?;
+    }
+
+    Ok(())
+}
+
+// Quiet, the switch itself is already reported
+fn on_branch_switch(
+    args: &InstallUpdateArgs,
+    script: Option<&Script>,
+    from: &str,
+    to: &str,
+) -> DotManResult<()> {
+    match script {
+        Some(script) if !(*args.no_scripts) => script.run_on_branch_switch(from, to),
+        _ => Ok(()),
+    }
+}
+
+// Brings the submodules in line with the commit that's checked out, if the package wants them
+fn submodules(pp: &Printer, pkg: &Package, wrapper: &GitWrapper) -> DotManResult<()> {
+    if !pkg.submodules {
+        return Ok(());
+    }
+
+    pp.info("Updating submodules...");
+    wrapper.update_submodules()?;
+    pp.success("Submodules have been updated!");
+
+    Ok(())
+}
+
+// The rebase has already been aborted, asks how the conflicts should be resolved and rebases again
+fn resolve_conflicts(
+    conf: &Config,
+    pp: &Printer,
+    wrapper: &GitWrapper,
+    remote: &str,
+    branch: &str,
+    path: String,
+    files: Vec<String>,
+) -> DotManResult<()> {
+    let action = match conf.git.update_type {
+        GitUpdateType::FetchRebase => "Rebasing onto",
+        GitUpdateType::Pull => "Merging",
+    };
+    pp.warning(&format!(
+        "{action} '{remote}/{branch}' conflicts in:\n\t{}",
+        files.join("\n\t")
     ));
 
-    let script = match Script::load(&install_path, &pp) {
-        Ok(s) => Some(s),
-        Err(Error::MissingScript) => None,
-        Err(e) => return Err(e),
+    let editor = env::var("EDITOR").unwrap_or("vi".to_owned());
+    let options = vec![
+        format!("Resolve them in {editor}"),
+        "Keep local changes".to_owned(),
+        "Keep upstream changes".to_owned(),
+        "Abort".to_owned(),
+    ];
+
+    let answer =
+        match inquire::Select::new("How do you want to resolve them?", options.clone()).prompt() {
+            Ok(a) => a,
+            Err(inquire::InquireError::OperationInterrupted) => options[3].clone(),
+            Err(e) => return Err(Error::Inquire(e)),
+        };
+
+    let edit = |files: &[String]| -> bool {
+        pp.info(&format!(
+            "Opening {} in {editor}, remove the conflict markers, save and quit...",
+            files.join(", ").italic()
+        ));
+
+        let mut words = editor.split_whitespace();
+        let status = Command::new(words.next().unwrap_or("vi"))
+            .args(words)
+            .args(files)
+            .current_dir(wrapper.path())
+            .status();
+
+        match status {
+            Ok(s) => s.success(),
+            Err(e) => {
+                Error::from(e).print_error();
+                false
+            }
+        }
+    };
+
+    let resolution = match options.iter().position(|o| *o == answer) {
+        Some(0) => Resolution::Edit(&edit),
+        Some(1) => Resolution::KeepLocal,
+        Some(2) => Resolution::KeepUpstream,
+        _ => return Err(Error::Git(GitError::Conflict(path, files))),
     };
 
-    GitWrapper::clone(&pkg.url(), &pkg.install_path()?)?;
+    match conf.git.update_type {
+        GitUpdateType::FetchRebase => wrapper.rebase(remote, branch, &resolution),
+        GitUpdateType::Pull => wrapper.merge(remote, branch, &resolution),
+    }
+}
+
+// Describes the uncommitted changes and unpushed commits of a package, if it has any
+fn local_changes(wrapper: &GitWrapper, remote: &str) -> DotManResult<Option<String>> {
+    let mut changes = vec![];
+
+    if wrapper.is_dirty()? {
+        changes.push("uncommitted changes".to_owned());
+    }
+
+    match wrapper.unpushed_commits(remote)? {
+        0 => {}
+        1 => changes.push("1 unpushed commit".to_owned()),
+        n => changes.push(format!("{n} unpushed commits")),
+    }
+
+    if changes.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(changes.join(" and ")))
+    }
+}
+
+// A package waiting to be cloned, or fetched if `existing` is set
+struct Task {
+    pkg: Package,
+    detached: Option<String>,
+    existing: Option<GitWrapper>,
+}
+
+enum Fetched {
+    Cloned(GitWrapper),
+    Updated {
+        wrapper: GitWrapper,
+        remote: String,
+        branch: String,
+    },
+}
+
+// Clones and fetches up to `jobs` packages at the same time, with one progress bar each.
+// The results are in the same order as `tasks`
+fn fetch_all(conf: &Config, tasks: Vec<Task>, jobs: usize) -> Vec<(Task, DotManResult<Fetched>)> {
+    let multi = MultiProgress::new();
+    let style = ProgressStyle::with_template("{spinner} {prefix:.bold} {msg}")
+        .unwrap_or_else(|_| ProgressStyle::default_spinner());
+
+    let queue = Mutex::new(
+        tasks
+            .into_iter()
+            .enumerate()
+            .map(|(i, task)| {
+                let pb = multi.add(ProgressBar::new_spinner().with_style(style.clone()));
+                pb.set_prefix(task.pkg.name.clone());
+                pb.set_message("Waiting...");
+                pb.enable_steady_tick(Duration::from_millis(100));
+                (i, task, pb)
+            })
+            .collect::<VecDeque<_>>(),
+    );
+    let results = Mutex::new(vec![]);
+
+    thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            s.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((i, mut task, pb)) = next else {
+                    break;
+                };
+
+                let result = fetch(conf, &mut task, &pb);
+                pb.finish_with_message(match &result {
+                    Ok(Fetched::Cloned(_)) => "Cloned".green().to_string(),
+                    Ok(Fetched::Updated { .. }) => "Fetched".green().to_string(),
+                    Err(_) => "Failed".red().to_string(),
+                });
+
+                results.lock().unwrap().push((i, task, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _, _)| *i);
+    results
+        .into_iter()
+        .map(|(_, task, result)| (task, result))
+        .collect()
+}
+
+fn fetch(conf: &Config, task: &mut Task, pb: &ProgressBar) -> DotManResult<Fetched> {
+    let pkg = &task.pkg;
+
+    let wrapper = match task.existing.take() {
+        Some(wrapper) => wrapper,
+        None => {
+            pb.set_message(format!("Cloning from {}...", pkg.url()));
+
+            // Tags are fetched by the clone and checked out later
+            let branch = pkg.branch.as_ref().or(conf.branch.as_ref());
+            let wrapper = GitWrapper::clone(
+                &pkg.url(),
+                &pkg.install_path()?,
+                branch.map(|b| b.as_str()),
+                // libgit2 can't fetch shallow from a local repository
+                pkg.clone_depth(conf).filter(|_| !pkg.repo.is_local()),
+                &pkg.sparse_paths(),
+            )?;
+
+            return Ok(Fetched::Cloned(wrapper));
+        }
+    };
+
+    pb.set_message("Fetching...");
+
+    let remote = wrapper.get_remote_name()?;
+    let branch = pkg.branch(conf, &wrapper, &remote)?;
+    match (&task.detached, pkg.pin()) {
+        (Some(_), Some(Pin::Tag(tag))) => wrapper.fetch_tag(&remote, &tag)?,
+        _ => wrapper.fetch(&remote, &branch)?,
+    }
+
+    Ok(Fetched::Updated {
+        wrapper,
+        remote,
+        branch,
+    })
+}
+
+// Everything after the clone of a freshly installed package
+fn installed(
+    pp: &Printer,
+    pkg: &Package,
+    args: &InstallUpdateArgs,
+    detached: Option<&str>,
+    wrapper: &GitWrapper,
+) -> DotManResult<()> {
+    let install_path = pkg.install_path()?;
+    let url = pkg.url();
+
+    if let Some(target) = detached {
+        pp.info(&format!("Checking out '{}'...", target.italic()));
+        wrapper.checkout_detached(target)?;
+    }
+
+    // Only now, so a pinned package runs the `.dotman.lua` it's pinned to
+    let script = load_script(&install_path, pp)?;
+
+    hook(
+        pp,
+        args,
+        script.as_ref(),
+        "pre_install",
+        Script::run_preinstall,
+    )?;
 
     pp.success(&format!(
         "{} cloned from {} to {}! ",
@@ -203,6 +716,8 @@ fn clone(pp: &Printer, pkg: &Package, args: &InstallUpdateArgs) -> DotManResult<
         install_path.italic()
     ));
 
+    submodules(pp, pkg, wrapper)?;
+
     if !(*args.no_scripts) {
         pp.info(&format!(
             "Running `{}` function if it exists...",
@@ -226,3 +741,156 @@ fn clone(pp: &Printer, pkg: &Package, args: &InstallUpdateArgs) -> DotManResult<
 
     Ok(())
 }
+
+// Describes what `install_or_update` would do for every package without touching anything
+fn plan(
+    conf: &Config,
+    repo: &Repository,
+    args: &InstallUpdateArgs,
+    packages: &Vec<String>,
+    lockfile: &Lockfile,
+) -> DotManResult<()> {
+    print::warning("Dry run, nothing will be changed...");
+
+    if *args.locked {
+        for (source, commit) in &lockfile.sources {
+            if repo.commits.get(source).is_some_and(|c| c != commit) {
+                print::info(&format!(
+                    "Source '{}' would be checked out at '{}'",
+                    source.bold(),
+                    commit.italic()
+                ));
+            }
+        }
+    } else {
+        for source in &repo.sources {
+            let git = GitWrapper::open(&source.url(), &source.path()?)?;
+            if git.current_branch_name()?.is_empty() {
+                print::info(&format!(
+                    "Source '{}' would be switched back to its branch",
+                    source.name.bold()
+                ));
+            }
+        }
+    }
+
+    for pkg in packages {
+        let pkg = repo.get_package_from_string(pkg);
+        let install_path = pkg.install_path()?;
+        let pp = Printer::new(pkg.name.clone());
+
+        let locked_commit = if *args.locked {
+            Some(lockfile.commit(&repo.package_id(&pkg))?.to_owned())
+        } else {
+            None
+        };
+        let detached = locked_commit.or(pkg.detached_ref());
+
+        let hooks = match GitWrapper::open(&pkg.url(), &install_path) {
+            Err(Error::Git(GitError::NotARepository(_))) => {
+                if !(*args.force) {
+                    pp.error(&format!(
+                        "'{}' exists but isn't a dotman repo, would exit without --force",
+                        install_path.italic()
+                    ));
+                    continue;
+                }
+
+                pp.warning(&format!(
+                    "'{}' exists but isn't a dotman repo, would be backed up to '{}'",
+                    install_path.italic(),
+                    backup::backups_dir()?.italic()
+                ));
+                pp.info(&format!(
+                    "Would be cloned from {} to {}",
+                    pkg.url().italic(),
+                    install_path.italic()
+                ));
+                ("pre_install", "post_install")
+            }
+            Err(Error::IO(e)) if e.kind() == io::ErrorKind::NotFound => {
+                pp.info(&format!(
+                    "Would be cloned from {} to {}",
+                    pkg.url().italic(),
+                    install_path.italic()
+                ));
+                ("pre_install", "post_install")
+            }
+            Err(e) => return Err(e),
+            Ok(wrapper) => {
+                let remote = wrapper.get_remote_name()?;
+                let branch = pkg.peek_branch(conf, &wrapper, &remote)?;
+
+                if let Some(changes) = local_changes(&wrapper, &remote)? {
+                    let policy = args.local_changes.unwrap_or(conf.git.local_changes);
+                    match policy {
+                        LocalChanges::Abort => {
+                            pp.error(&format!("Has {changes}, would exit"));
+                            continue;
+                        }
+                        LocalChanges::Skip => {
+                            pp.warning(&format!("Has {changes}, would be skipped"));
+                            continue;
+                        }
+                        LocalChanges::Autostash => pp.info(&format!(
+                            "Has {changes}, uncommitted changes would be stashed"
+                        )),
+                        LocalChanges::Reset => {
+                            pp.warning(&format!("Has {changes}, they would be thrown away"))
+                        }
+                    }
+                }
+
+                let current_branch = wrapper.current_branch_name()?;
+                if detached.is_none() && current_branch != branch {
+                    let from = if current_branch.is_empty() {
+                        "a detached HEAD".to_owned()
+                    } else {
+                        format!("'{}' branch", current_branch.italic())
+                    };
+
+                    pp.info(&format!(
+                        "Would switch from {} to '{}' branch",
+                        from,
+                        branch.bold()
+                    ));
+                }
+
+                match (&detached, &conf.git.update_type) {
+                    (Some(target), _) => {
+                        pp.info(&format!("Would fetch and check out '{}'", target.italic()))
+                    }
+                    (None, GitUpdateType::FetchRebase) => {
+                        pp.info("Would be updated by fetching and rebasing")
+                    }
+                    (None, GitUpdateType::Pull) => pp.info("Would be updated by pulling"),
+                }
+
+                if detached.is_none() && current_branch != branch && !current_branch.is_empty() {
+                    pp.info(&format!(
+                        "Would switch back to '{}' branch",
+                        current_branch.italic()
+                    ));
+                }
+
+                ("pre_update", "post_update")
+            }
+        };
+
+        if pkg.submodules {
+            pp.info("Would update submodules");
+        }
+
+        let (pre, post) = (
+            format!(".dotman.lua:M.{}", hooks.0).italic(),
+            format!(".dotman.lua:M.{}", hooks.1).italic(),
+        );
+        if *args.no_scripts {
+            pp.info(&format!("Wouldn't run `{pre}` or `{post}`"));
+        } else {
+            pp.info(&format!("Would run `{pre}` and `{post}` if they exist"));
+        }
+    }
+
+    Ok(())
+}
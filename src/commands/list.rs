@@ -0,0 +1,149 @@
+use colored::Colorize;
+use indicatif::ProgressBar;
+
+use crate::{
+    cli::ListArgs,
+    config::Config,
+    errors::DotManResult,
+    gitactions::{Git, GitWrapper},
+    package::{Package, Pin},
+    print,
+    repo::Repository,
+    state::{InstallReason, State},
+};
+
+pub fn list(conf: &Config, repo: &Repository, args: ListArgs) -> DotManResult<()> {
+    let state = State::load()?;
+    let only_installed = *args.installed || *args.explicit || *args.deps || *args.outdated;
+
+    let mut installed_count = 0;
+    let mut listed = 0;
+
+    // Packages hidden by one from a source with higher priority are only listed once installed
+    let packages = repo
+        .all_packages()
+        .filter(|pkg| {
+            let id = repo.package_id(pkg);
+            id == pkg.name || state.is_installed(&id)
+        })
+        .collect::<Vec<_>>();
+
+    for pkg in &packages {
+        let id = repo.package_id(pkg);
+        let installed = pkg.is_installed()?;
+        if installed {
+            installed_count += 1;
+        }
+
+        if only_installed && !installed {
+            continue;
+        }
+
+        if *args.available && installed {
+            continue;
+        }
+
+        let reason = state.get(&id).map(|p| p.reason);
+        if *args.explicit && reason != Some(InstallReason::Explicit) {
+            continue;
+        }
+
+        if *args.deps && reason != Some(InstallReason::Dependency) {
+            continue;
+        }
+
+        let mut status = vec![];
+        if installed {
+            status.push(
+                match reason {
+                    Some(InstallReason::Explicit) => "installed",
+                    Some(InstallReason::Dependency) => "installed as dependency",
+                    None => "installed, untracked",
+                }
+                .green()
+                .to_string(),
+            );
+        }
+
+        if *args.outdated {
+            let behind = commits_behind(conf, pkg)?;
+            if behind == 0 {
+                continue;
+            }
+
+            status.push(format!("{behind} commits behind").yellow().to_string());
+        }
+
+        listed += 1;
+
+        let status = if status.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", status.join(", "))
+        };
+
+        print::info(&format!(
+            "{} - {}{}",
+            id.blue().bold(),
+            pkg.description.italic(),
+            status
+        ));
+    }
+
+    if !only_installed && !*args.available {
+        for col in &repo.collections {
+            let count = col
+                .packages
+                .iter()
+                .filter_map(|name| repo.try_get_package_from_string(name))
+                .map(|pkg| pkg.is_installed())
+                .collect::<DotManResult<Vec<bool>>>()?
+                .into_iter()
+                .filter(|installed| *installed)
+                .count();
+
+            print::info(&format!(
+                "{} - {} [{}/{} installed]",
+                col.name.bold().yellow(),
+                col.description.italic(),
+                count,
+                col.packages.len()
+            ));
+        }
+    }
+
+    print::info(&format!(
+        "{} packages listed, {} of {} packages installed",
+        listed.to_string().bold(),
+        installed_count.to_string().bold(),
+        packages.len().to_string().bold()
+    ));
+
+    Ok(())
+}
+
+fn commits_behind(conf: &Config, pkg: &Package) -> DotManResult<usize> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_message(format!("Fetching {}...", pkg.name));
+
+    let wrapper = GitWrapper::open(&pkg.url(), &pkg.install_path()?)?;
+    let remote = wrapper.get_remote_name()?;
+
+    // Pinned packages are only behind when their tag has been moved, a commit can't move
+    let behind = match pkg.pin() {
+        Some(Pin::Rev(_)) => 0,
+        Some(Pin::Tag(tag)) => {
+            wrapper.fetch_tag(&remote, &tag)?;
+            wrapper.commits_behind_tag(&tag)?
+        }
+        _ => {
+            let branch = pkg.branch(conf, &wrapper, &remote)?;
+            wrapper.fetch(&remote, &branch)?;
+            wrapper.commits_behind(&remote, &branch)?
+        }
+    };
+
+    pb.finish_and_clear();
+
+    Ok(behind)
+}
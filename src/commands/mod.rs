@@ -1,9 +1,21 @@
+mod graph;
 mod inspect;
 mod install;
+mod list;
+mod restore;
 mod script;
 mod search;
+mod uninstall;
+mod validate;
+mod why;
 
+pub use graph::graph;
 pub use inspect::inspect;
 pub use install::install_or_update;
+pub use list::list;
+pub use restore::restore;
 pub use script::script;
 pub use search::search;
+pub use uninstall::uninstall;
+pub use validate::validate;
+pub use why::{rdeps, why};
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use colored::Colorize;
+
+use crate::{
+    backup,
+    errors::{DotManResult, Error},
+    print::{self, Printer},
+    state::State,
+};
+
+pub fn restore(package: &str, force: &bool) -> DotManResult<()> {
+    let mut state = State::load()?;
+    let pp = Printer::new(package.to_owned());
+
+    let Some(latest) = backup::latest(&state, package) else {
+        return Err(Error::NoBackup(package.to_owned()));
+    };
+
+    if Path::new(&latest.original_path).exists() {
+        if !(*force) {
+            return Err(Error::RestoreTargetExists(latest.original_path));
+        }
+
+        let destination = backup::backup(&mut state, package, &latest.original_path)?;
+        pp.warning(&format!(
+            "'{}' exists, backed it up to '{}'...",
+            latest.original_path.italic(),
+            destination.italic()
+        ));
+    }
+
+    pp.info(&format!(
+        "Restoring '{}' to '{}'...",
+        latest.path.italic(),
+        latest.original_path.italic()
+    ));
+    backup::restore(&mut state, package, &latest)?;
+
+    // What's restored isn't a dotman repo, so it's no longer installed by dotman
+    if state
+        .get(package)
+        .is_some_and(|p| p.install_path == latest.original_path)
+    {
+        state.remove(package);
+        state.save()?;
+    }
+
+    print::success(&format!(
+        "{} has been successfully restored!",
+        package.bold().italic()
+    ));
+
+    Ok(())
+}
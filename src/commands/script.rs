@@ -1,6 +1,12 @@
-use crate::{cli::Function, errors::Error, print::Printer, repo::Repository, script::Script};
+use crate::{
+    cli::Function,
+    errors::{DotManResult, Error},
+    print::Printer,
+    repo::Repository,
+    script::Script,
+};
 
-pub fn script(repo: &Repository, packages: &Vec<String>, function: &Function) {
+pub fn script(repo: &Repository, packages: &Vec<String>, function: &Function) -> DotManResult<()> {
     for pkg_name in packages {
         let pp = Printer::new(pkg_name.clone());
 
@@ -11,30 +17,25 @@ pub fn script(repo: &Repository, packages: &Vec<String>, function: &Function) {
             continue;
         };
 
-        let path = pkg.install_path().expect("install_path");
+        let path = pkg.install_path()?;
         let script = match Script::load(&path, &pp) {
             Ok(s) => s,
             Err(Error::MissingScript) => {
                 pp.warning("No .dotman.lua... skipping");
                 continue;
             }
-            Err(e) => {
-                e.print_error();
-                continue;
-            }
+            Err(e) => return Err(e),
         };
 
-        let err = match function {
-            Function::PostUpdate => script.run_postupdate(),
+        match function {
+            Function::PreInstall => script.run_preinstall(),
             Function::PostInstall => script.run_postinstall(),
-        };
-
-        match err {
-            Ok(_) => {}
-            Err(e) => {
-                e.print_error();
-                continue;
-            }
-        }
+            Function::PreUpdate => script.run_preupdate(),
+            Function::PostUpdate => script.run_postupdate(),
+            Function::PreUninstall => script.run_preuninstall(),
+            Function::PostUninstall => script.run_postuninstall(),
+        }?;
     }
+
+    Ok(())
 }
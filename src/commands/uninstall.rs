@@ -0,0 +1,199 @@
+use std::{fs, io};
+
+use colored::Colorize;
+
+use crate::{
+    cli::UninstallArgs,
+    errors::{DotManResult, Error},
+    gitactions::GitWrapper,
+    package::Package,
+    print::{self, Printer},
+    repo::Repository,
+    script::Script,
+    state::State,
+};
+
+pub fn uninstall(
+    repo: &Repository,
+    args: UninstallArgs,
+    packages: &Vec<String>,
+) -> DotManResult<()> {
+    let mut state = State::load()?;
+    let mut installed = vec![];
+    for pkg in repo.all_packages() {
+        // Still recorded but deleted by hand, uninstalling it cleans up the state file
+        if pkg.is_installed()? || state.is_installed(&repo.package_id(pkg)) {
+            installed.push(pkg.clone());
+        }
+    }
+
+    let mut to_remove: Vec<Package> = vec![];
+    for name in packages {
+        let Some(pkg) = repo.try_get_package_from_string(name) else {
+            return Err(Error::UnknownPackage(name.to_owned()));
+        };
+
+        let id = repo.package_id(&pkg);
+        if !installed.iter().any(|p| repo.package_id(p) == id) {
+            print::warning(&format!("{} isn't installed, skipping...", name.bold()));
+            continue;
+        }
+
+        if !to_remove.iter().any(|p| repo.package_id(p) == id) {
+            to_remove.push(pkg);
+        }
+    }
+
+    // Packages are appended as they are discovered, so dependents always end up
+    // after the packages they depend on.
+    let mut i = 0;
+    while i < to_remove.len() {
+        let id = repo.package_id(&to_remove[i]);
+        let dependents = installed
+            .iter()
+            .filter(|p| {
+                p.dependencies.iter().any(|d| {
+                    repo.try_get_package_from_string(d)
+                        .is_some_and(|d| repo.package_id(&d) == id)
+                })
+            })
+            .filter(|p| {
+                !to_remove
+                    .iter()
+                    .any(|r| repo.package_id(r) == repo.package_id(p))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !dependents.is_empty() {
+            if !(*args.cascade) {
+                return Err(Error::RequiredBy(
+                    id,
+                    dependents.iter().map(|p| repo.package_id(p)).collect(),
+                ));
+            }
+
+            to_remove.extend(dependents);
+        }
+
+        i += 1;
+    }
+
+    if to_remove.is_empty() {
+        print::info("Nothing to uninstall...");
+        return Ok(());
+    }
+
+    let packages_string = to_remove
+        .iter()
+        .map(|p| p.name.clone().italic().bold().to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    print::info(&format!(
+        "Packages ({}) {} will be uninstalled.",
+        to_remove.len(),
+        packages_string
+    ));
+
+    if !(*args.yes) {
+        let confirm = inquire::Confirm::new("Do you want to procced?")
+            .with_default(false)
+            .with_help_message("This will remove the installed configurations");
+
+        let result = match confirm.prompt() {
+            Ok(r) => r,
+            Err(inquire::InquireError::OperationInterrupted) => {
+                print::info("Okay, exiting...");
+                return Ok(());
+            }
+            Err(e) => return Err(Error::Inquire(e)),
+        };
+
+        if !result {
+            print::info("Okay, exiting...");
+            return Ok(());
+        }
+    } else {
+        print::warning("Running with no confirmation...");
+    }
+
+    for pkg in to_remove.iter().rev() {
+        let install_path = pkg.install_path()?;
+        let pp = Printer::new(pkg.name.clone());
+
+        let script = match Script::load(&install_path, &pp) {
+            Ok(s) => Some(s),
+            Err(Error::MissingScript) => None,
+            Err(e) => return Err(e),
+        };
+
+        if !(*args.no_scripts) {
+            pp.info(&format!(
+                "Running `{}` function if it exists...",
+                ".dotman.lua:M.pre_uninstall".italic()
+            ));
+            if let Some(script) = &script {
+                script.run_preuninstall()?;
+            }
+        }
+
+        // Only ever delete the package's own repo, something else might have taken its place
+        match GitWrapper::open(&pkg.url(), &install_path) {
+            Ok(_) => {
+                pp.info(&format!("Removing '{}'...", install_path.italic()));
+                fs::remove_dir_all(&install_path)?;
+            }
+            Err(Error::IO(e)) if e.kind() == io::ErrorKind::NotFound => pp.warning(&format!(
+                "'{}' is already gone, forgetting it...",
+                install_path.italic()
+            )),
+            Err(e) => return Err(e),
+        }
+        state.remove(&repo.package_id(pkg));
+        state.save()?;
+
+        if !(*args.no_scripts) {
+            pp.info(&format!(
+                "Running `{}` function if it exists...",
+                ".dotman.lua:M.post_uninstall".italic()
+            ));
+            if let Some(script) = &script {
+                script.run_postuninstall()?;
+            }
+        } else {
+            pp.warning(&format!(
+                "Not running `{}` may leave symlinks behind...",
+                ".dotman.lua:M.post_uninstall".italic()
+            ));
+        }
+
+        print::success(&format!(
+            "{} has been successfully uninstalled!",
+            pkg.name.bold().italic()
+        ));
+    }
+
+    // Collections are forgotten once none of their packages are installed
+    let forgotten = state
+        .collections
+        .iter()
+        .filter(|name| {
+            !repo.try_get_collection_from_string(name).is_some_and(|c| {
+                c.packages.iter().any(|p| {
+                    repo.try_get_package_from_string(p)
+                        .is_some_and(|p| state.is_installed(&repo.package_id(&p)))
+                })
+            })
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if !forgotten.is_empty() {
+        for name in forgotten {
+            state.collections.remove(&name);
+        }
+        state.save()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use colored::Colorize;
+
+use crate::{
+    errors::{DotManResult, Error},
+    print::{self, Printer},
+    repo::{self, Repository, RepositorySettings},
+    required_packages,
+};
+
+/// Checks a repository checkout and reports every problem found instead of stopping at the first
+pub fn validate(path: &str) -> DotManResult<()> {
+    let root = PathBuf::from(path);
+    let mut problems = 0;
+
+    let settings = match RepositorySettings::load(&root) {
+        Ok(settings) => settings,
+        Err(Error::MalformattedPackageWithError(name, e)) => {
+            let content = fs::read_to_string(root.join(&name))?;
+            toml_problem(&name, &content, &e);
+            problems += 1;
+            RepositorySettings::default()
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut paths = vec![];
+    repo::find_package_files(&root, &mut paths)?;
+
+    // Name -> file it was defined in
+    let mut names: BTreeMap<String, String> = BTreeMap::new();
+    let mut files = vec![];
+
+    for path in &paths {
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        let name = relative.to_string_lossy().to_string();
+        let content = fs::read_to_string(path)?;
+
+        let file = match repo::parse_package_file(&content, relative, &settings) {
+            Ok(file) => file,
+            Err(Error::MalformattedPackageWithError(_, e)) => {
+                toml_problem(&name, &content, &e);
+                problems += 1;
+                continue;
+            }
+            Err(Error::MalformattedPackage(_)) => {
+                problem(
+                    &name,
+                    "must contain exactly one of [Package] or [Collection], and a package can only set one of `branch`, `tag` and `rev`",
+                );
+                problems += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let defined = file.name();
+        if let Some(other) = names.get(&defined) {
+            problem(
+                &name,
+                &format!("'{}' is already defined in '{}'", defined.bold(), other),
+            );
+            problems += 1;
+            continue;
+        }
+
+        if let Some(pkg) = &file.package {
+            if !(pkg.install_path.starts_with("$HOME") || pkg.install_path.starts_with('~')) {
+                problem(
+                    &name,
+                    &format!(
+                        "install-path '{}' should start with `$HOME` or `~`",
+                        pkg.install_path.italic()
+                    ),
+                );
+                problems += 1;
+            }
+        }
+
+        names.insert(defined, name);
+        files.push(file);
+    }
+
+    // Unknown names are removed after being reported so they don't hide cycles
+    for file in &mut files {
+        if let Some(pkg) = &mut file.package {
+            for dep in pkg.dependencies.iter().filter(|d| !names.contains_key(*d)) {
+                problem(
+                    &names[&pkg.name],
+                    &format!("'{}' depends on unknown '{}'", pkg.name.bold(), dep.bold()),
+                );
+                problems += 1;
+            }
+
+            pkg.dependencies.retain(|d| names.contains_key(d));
+        }
+
+        if let Some(col) = &mut file.collection {
+            for member in col.packages.iter().filter(|p| !names.contains_key(*p)) {
+                problem(
+                    &names[&col.name],
+                    &format!("'{}' contains unknown '{}'", col.name.bold(), member.bold()),
+                );
+                problems += 1;
+            }
+
+            col.packages.retain(|p| names.contains_key(p));
+        }
+    }
+
+    let repo = Repository::from_files(files);
+
+    // The same cycle is found from every package in it, only report it once
+    let mut cycles: HashSet<BTreeSet<String>> = HashSet::new();
+    for pkg in &repo.packages {
+        if let Err(Error::DependencyCycle(cycle)) =
+            required_packages::gather_required_packages(&vec![pkg.name.clone()], &repo)
+        {
+            if cycles.insert(cycle.iter().cloned().collect()) {
+                problem(
+                    &names[&cycle[0]],
+                    &format!("dependency cycle {}", cycle.join(" -> ").bold()),
+                );
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(Error::ValidationFailed(problems));
+    }
+
+    print::success(&format!(
+        "{} files checked, no problems found!",
+        paths.len().to_string().bold()
+    ));
+
+    Ok(())
+}
+
+fn problem(file: &str, message: &str) {
+    Printer::new(file.to_owned()).error(message);
+}
+
+fn toml_problem(file: &str, content: &str, e: &toml::de::Error) {
+    let location = match e.span() {
+        Some(span) => {
+            let before = &content[..span.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            format!("{file}:{line}:{column}")
+        }
+        None => file.to_owned(),
+    };
+
+    problem(&location, e.message());
+}
@@ -0,0 +1,118 @@
+use colored::Colorize;
+
+use crate::{
+    errors::{DotManResult, Error},
+    print,
+    repo::Repository,
+    required_packages,
+    state::{InstallReason, State},
+};
+
+pub fn why(repo: &Repository, package: &str, from: &[String]) -> DotManResult<()> {
+    if repo.try_get_package_from_string(package).is_none() {
+        return Err(Error::UnknownPackage(package.to_owned()));
+    }
+
+    let state = State::load()?;
+
+    // Without --from, look at why it was installed on this machine
+    let roots = if from.is_empty() {
+        if state.get(package).map(|p| p.reason) == Some(InstallReason::Explicit) {
+            print::info(&format!("{} was installed explicitly", package.bold()));
+        }
+
+        // Packages installed through a collection are explained by the collection
+        let collections = state
+            .collections
+            .iter()
+            .filter_map(|name| repo.try_get_collection_from_string(name))
+            .collect::<Vec<_>>();
+        let packages = state
+            .packages
+            .iter()
+            .filter(|(_, p)| p.reason == InstallReason::Explicit)
+            .map(|(name, _)| name)
+            .filter(|name| !collections.iter().any(|c| c.packages.contains(name)));
+
+        collections
+            .iter()
+            .map(|c| c.name.clone())
+            .chain(packages.cloned())
+            .collect::<Vec<_>>()
+    } else {
+        from.to_vec()
+    };
+
+    let mut found = false;
+    for root in &roots {
+        if root == package {
+            continue;
+        }
+
+        let Some(path) = required_packages::dependency_path(root, package, repo)? else {
+            continue;
+        };
+
+        found = true;
+        print::info(
+            &path
+                .iter()
+                .map(|name| format_name(name, repo))
+                .collect::<Vec<_>>()
+                .join(" -> "),
+        );
+    }
+
+    if !found {
+        print::info(&format!(
+            "Nothing {} requires {}",
+            if from.is_empty() {
+                "installed"
+            } else {
+                "selected"
+            },
+            package.bold()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn rdeps(repo: &Repository, package: &str) -> DotManResult<()> {
+    if repo.try_get_package_from_string(package).is_none() {
+        return Err(Error::UnknownPackage(package.to_owned()));
+    }
+
+    let dependents = required_packages::reverse_dependencies(package, repo);
+
+    if dependents.is_empty() {
+        print::info(&format!("Nothing depends on {}", package.bold()));
+        return Ok(());
+    }
+
+    print::info(&format!(
+        "{} packages and collections depend on {}",
+        dependents.len().to_string().bold(),
+        package.bold()
+    ));
+
+    for (name, depth) in dependents {
+        let kind = if depth == 1 { "direct" } else { "indirect" };
+        print::info(&format!(
+            "  {} ({})",
+            format_name(&name, repo),
+            kind.italic()
+        ));
+    }
+
+    Ok(())
+}
+
+// Same colors as `Package::pprint` and `Collection::pprint`
+fn format_name(name: &str, repo: &Repository) -> String {
+    if repo.try_get_collection_from_string(name).is_some() {
+        name.bold().yellow().to_string()
+    } else {
+        name.blue().bold().to_string()
+    }
+}
@@ -2,16 +2,29 @@ use std::{env::var, fs, io};
 
 use crate::{
     errors::{DotManResult, Error},
-    remote::Remote,
+    remote::{Protocol, Remote},
+    source::Source,
     utils,
 };
+use clap::ValueEnum;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
-    remote: Remote,
-    path: String,
+    remote: Option<Remote>,
+    path: Option<String>,
+    #[serde(default, rename = "source")]
+    sources: Vec<Source>,
+    lockfile: Option<String>,
+    /// Branch used for the repository and for packages that don't set their own
+    pub branch: Option<String>,
+    /// Used by remotes that don't set their own
+    #[serde(default = "Protocol::default")]
+    protocol: Protocol,
+    // Set by `--https`, overrides the protocol of every remote
+    #[serde(skip)]
+    https: bool,
 
     pub git: GitConfig,
 }
@@ -27,12 +40,63 @@ impl Config {
         Ok(toml::from_str(&content)?)
     }
 
-    pub fn url(&self) -> String {
-        self.remote.url()
+    /// Every `[[source]]` and the top level `remote` and `path`, if set, as a source
+    /// named `default`. Highest priority first, ties keep the order of the config file
+    pub fn sources(&self) -> DotManResult<Vec<Source>> {
+        let mut sources = vec![];
+
+        match (&self.remote, &self.path) {
+            (Some(remote), Some(path)) => sources.push(Source {
+                name: "default".to_owned(),
+                remote: remote.clone(),
+                path: path.clone(),
+                priority: 0,
+            }),
+            (None, None) => {}
+            _ => return Err(Error::MissingSource),
+        }
+
+        for source in &self.sources {
+            if sources.iter().any(|s: &Source| s.name == source.name) {
+                return Err(Error::DuplicateSource(source.name.clone()));
+            }
+
+            sources.push(source.clone());
+        }
+
+        for source in &mut sources {
+            let protocol = self.protocol(&source.remote);
+            source.remote.set_protocol(protocol);
+        }
+
+        if sources.is_empty() {
+            return Err(Error::MissingSource);
+        }
+
+        sources.sort_by_key(|s| std::cmp::Reverse(s.priority));
+
+        Ok(sources)
+    }
+
+    /// Clone everything over https, e.g. on a machine without ssh keys
+    pub fn force_https(&mut self) {
+        self.https = true;
+    }
+
+    /// The protocol to use for `remote`
+    pub fn protocol(&self, remote: &Remote) -> Protocol {
+        if self.https {
+            return Protocol::Https;
+        }
+
+        remote.protocol().unwrap_or(self.protocol)
     }
 
-    pub fn path(&self) -> DotManResult<String> {
-        utils::expand("", &self.path)
+    pub fn lockfile(&self) -> DotManResult<String> {
+        match &self.lockfile {
+            Some(path) => utils::expand("", path),
+            None => utils::expand("", "~/.config/dotman/dotman.lock"),
+        }
     }
 }
 
@@ -41,6 +105,23 @@ impl Config {
 pub struct GitConfig {
     #[serde(default = "GitUpdateType::default")]
     pub update_type: GitUpdateType,
+
+    /// Number of packages cloned or fetched at the same time
+    #[serde(default = "GitConfig::default_jobs")]
+    pub jobs: usize,
+
+    /// What to do with a package that has uncommitted changes or unpushed commits
+    #[serde(default = "LocalChanges::default")]
+    pub local_changes: LocalChanges,
+
+    /// Number of commits cloned for packages that don't set their own
+    pub clone_depth: Option<u32>,
+}
+
+impl GitConfig {
+    pub fn default_jobs() -> usize {
+        4
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,3 +136,22 @@ impl GitUpdateType {
         GitUpdateType::FetchRebase
     }
 }
+
+#[derive(Debug, Deserialize, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LocalChanges {
+    /// Stash uncommitted changes during the update and reapply them afterwards
+    Autostash,
+    /// Leave the package as it is and carry on with the others
+    Skip,
+    /// Stop before anything is changed
+    Abort,
+    /// Throw the changes away and reset to the remote branch
+    Reset,
+}
+
+impl LocalChanges {
+    pub fn default() -> LocalChanges {
+        LocalChanges::Abort
+    }
+}
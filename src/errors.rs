@@ -7,25 +7,69 @@ use crate::print;
 #[derive(Debug)]
 pub enum GitError {
     NotARepository(String),
-    Unknown(String),
+    Authentication(GitFailure),
+    HostUnreachable(GitFailure),
+    Diverged(GitFailure),
+    DirtyTree(GitFailure),
+    MissingRef(GitFailure),
+    /// Path of the repository and the files with conflicts
+    Conflict(String, Vec<String>),
+    Unknown(GitFailure),
+}
+
+/// Everything libgit2 said about a failed operation
+#[derive(Debug)]
+pub struct GitFailure {
+    pub path: String,
+    /// The git command that does the same, e.g. `git fetch origin main`
+    pub command: String,
+    /// libgit2's error class and code, e.g. `Ssh/Auth`
+    pub status: String,
+    /// libgit2's description of the error, what git would print to stderr
+    pub message: String,
+}
+
+impl GitFailure {
+    // The details are only shown with --verbose, unless nothing else explains the failure
+    fn describe(&self, headline: &str, always_detailed: bool) -> String {
+        let mut description = format!("{headline}...\n\t`{}` in '{}'", self.command, self.path);
+        if always_detailed || print::is_verbose() {
+            description.push_str(&format!("\n\t{} ({})", self.message, self.status));
+        }
+
+        description
+    }
 }
 
 #[derive(Debug)]
 pub enum Error {
     ConfigFileNotFound,
+    MissingSource,
+    DuplicateSource(String),
     MissingHomeVariable,
     MissingScript,
 
     Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
     IO(std::io::Error),
     Utf8(FromUtf8Error),
     Inquire(InquireError),
 
     Git(GitError),
     RemoteNotFound(String),
+    NotADotmanRepo(String),
+    LocalChanges(String, String),
     MalformattedPackage(String),
     MalformattedPackageWithError(String, toml::de::Error),
     UnknownPackage(String),
+    DuplicateName(String, String, String),
+    ValidationFailed(usize),
+    LockfileNotFound(String),
+    NoBackup(String),
+    RestoreTargetExists(String),
+    NotLocked(String),
+    RequiredBy(String, Vec<String>),
+    DependencyCycle(Vec<String>),
 
     LuaError(mlua::Error),
     JSONError(serde_json::Error),
@@ -36,13 +80,41 @@ impl Error {
     fn print(&self, func: fn(&str) -> ()) {
         match self {
             Error::ConfigFileNotFound => func("Config file not found..."),
+            Error::DuplicateSource(name) => {
+                func(&format!("More than one source is named '{name}'..."))
+            }
+            Error::MissingSource => func(
+                "Config needs both `remote` and `path`, or at least one [[source]]...",
+            ),
             Error::MissingScript => func(".dotman.lua file not found..."),
             Error::ExpectedTable => func(".dotman.lua should return a table..."),
             Error::Parse(e) => func(&format!("Parse error...\n\t{}", e.message())),
+            Error::Serialize(e) => func(&format!("Serialize error...\n\t{}", e)),
             Error::IO(e) => func(&format!("IO error...\n\t{}", &e.to_string())),
             Error::Git(e) => match e {
                 GitError::NotARepository(message) => func(message),
-                GitError::Unknown(message) => func(message),
+                GitError::Authentication(f) => func(&f.describe(
+                    "Authentication failed, is your ssh key loaded in ssh-agent?",
+                    false,
+                )),
+                GitError::HostUnreachable(f) => func(&f.describe(
+                    "Couldn't reach the remote, check your network connection",
+                    false,
+                )),
+                GitError::Diverged(f) => {
+                    func(&f.describe("Local and upstream history have diverged", false))
+                }
+                GitError::DirtyTree(f) => {
+                    func(&f.describe("Working tree has uncommitted changes", false))
+                }
+                GitError::MissingRef(f) => {
+                    func(&f.describe("Branch, tag or commit not found", true))
+                }
+                GitError::Conflict(path, files) => func(&format!(
+                    "Conflicts in '{path}'...\n\t{}",
+                    files.join("\n\t")
+                )),
+                GitError::Unknown(f) => func(&f.describe("Git failed", true)),
             },
             Error::MissingHomeVariable => func("HOME environment variable isn't set"),
             Error::MalformattedPackageWithError(name, err) => {
@@ -51,13 +123,76 @@ impl Error {
             Error::MalformattedPackage(name) => func(&format!("'{name}' is malformatted...")),
             Error::Utf8(e) => func(&e.to_string()),
             Error::RemoteNotFound(message) => func(message),
+            Error::NotADotmanRepo(path) => func(&format!(
+                "'{path}' exists but isn't a dotman repo...\n\tUse --force to back it up and install anyway"
+            )),
+            Error::LocalChanges(p, changes) => func(&format!(
+                "'{p}' has {changes}...\n\tUse --local-changes to autostash, skip or reset it instead"
+            )),
             Error::UnknownPackage(p) => func(&format!("Package '{p}' can't be found...")),
+            Error::ValidationFailed(count) => func(&format!("{count} problems found...")),
+            Error::DuplicateName(name, first, second) => func(&format!(
+                "'{name}' is defined in both '{first}' and '{second}'..."
+            )),
+            Error::LockfileNotFound(path) => func(&format!("Lockfile '{path}' not found...")),
+            Error::NoBackup(p) => func(&format!("No backup of '{p}' exists...")),
+            Error::RestoreTargetExists(path) => func(&format!(
+                "'{path}' already exists...\n\tUse --force to back it up and restore anyway"
+            )),
+            Error::NotLocked(p) => func(&format!("Package '{p}' isn't in the lockfile...")),
+            Error::DependencyCycle(cycle) => func(&format!(
+                "Dependency cycle found...\n\t{}",
+                cycle.join(" -> ")
+            )),
+            Error::RequiredBy(p, dependents) => func(&format!(
+                "Package '{p}' is required by {}...\n\tUse --cascade to uninstall them too",
+                dependents.join(", ")
+            )),
             Error::Inquire(e) => func(&format!("Something went wrong with inquire...\n\t{}", e)),
             Error::LuaError(e) => func(&format!("Something went wrong in lua...\n\t{}", e)),
             Error::JSONError(e) => func(&format!("Something went wrong with json...\n\t{}", e)),
         }
     }
 
+    /// Process exit code, grouped by what kind of failure it is
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::ConfigFileNotFound
+            | Error::MissingSource
+            | Error::DuplicateSource(_)
+            | Error::MissingHomeVariable
+            | Error::Parse(_)
+            | Error::LockfileNotFound(_) => 2,
+
+            Error::Git(_)
+            | Error::RemoteNotFound(_)
+            | Error::NotADotmanRepo(_)
+            | Error::LocalChanges(_, _) => 3,
+
+            Error::MalformattedPackage(_)
+            | Error::MalformattedPackageWithError(_, _)
+            | Error::UnknownPackage(_)
+            | Error::DuplicateName(_, _, _)
+            | Error::ValidationFailed(_)
+            | Error::RequiredBy(_, _)
+            | Error::DependencyCycle(_)
+            | Error::NotLocked(_) => 4,
+
+            Error::MissingScript
+            | Error::ExpectedTable
+            | Error::LuaError(_)
+            | Error::JSONError(_) => 5,
+
+            Error::IO(_)
+            | Error::Utf8(_)
+            | Error::Serialize(_)
+            | Error::NoBackup(_)
+            | Error::RestoreTargetExists(_) => 6,
+
+            Error::Inquire(_) => 1,
+        }
+    }
+
     pub fn print_warning(&self) {
         self.print(print::warning)
     }
@@ -77,6 +212,12 @@ impl From<toml::de::Error> for Error {
     }
 }
 
+impl From<toml::ser::Error> for Error {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::Serialize(value)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self::IO(value)
@@ -1,20 +1,136 @@
 use std::{
-    path::Path,
-    process::{Command, Output},
+    fs, io,
+    path::{Path, PathBuf},
 };
 
-use crate::errors::{DotManResult, Error, GitError};
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    AutotagOption, BranchType, Cred, CredentialType, Direction, ErrorClass, ErrorCode,
+    FetchOptions, FileFavor, Index, IndexEntry, IndexEntryExtendedFlag, IndexTime,
+    MergeFileOptions, MergeOptions, Object, Oid, Pathspec, PathspecFlags, RebaseOptions,
+    RemoteCallbacks, Repository, ResetType, Signature, StashApplyOptions, StashSaveOptions, Status,
+    StatusOptions, SubmoduleUpdateOptions,
+};
+
+use crate::{
+    errors::{DotManResult, Error, GitError, GitFailure},
+    utils,
+};
+
+/// Everything dotman needs to do with a git repository
+pub trait Git {
+    /// Name of the current branch, empty if HEAD is detached
+    fn current_branch_name(&self) -> DotManResult<String>;
+
+    fn head_commit(&self) -> DotManResult<String>;
+
+    /// Checks out the local branch `name`, creating it from the remote branch
+    /// with the same name if it doesn't exist yet
+    fn checkout_branch(&self, name: &str) -> DotManResult<()>;
+
+    /// Checks out a commit, tag or any other revision on a detached HEAD
+    fn checkout_detached(&self, rev: &str) -> DotManResult<()>;
+
+    fn get_remote_name(&self) -> DotManResult<String>;
+
+    /// The branch `<remote>/HEAD` points to, `master` if the remote doesn't say
+    fn default_branch(&self, remote: &str) -> DotManResult<String>;
+
+    /// [`Git::default_branch`] without writing `<remote>/HEAD`, for `--dry-run`
+    fn peek_default_branch(&self, remote: &str) -> DotManResult<String>;
+
+    /// Fetches `tag`, replacing the local tag if it has been moved upstream
+    fn fetch_tag(&self, remote: &str, tag: &str) -> DotManResult<()>;
+
+    /// Fetches `branch` into `<remote>/<branch>`
+    fn fetch(&self, remote: &str, branch: &str) -> DotManResult<()>;
+
+    /// Number of commits on the fetched `branch` of `remote` that aren't in `HEAD`
+    fn commits_behind(&self, remote: &str, branch: &str) -> DotManResult<usize>;
+
+    /// Number of commits on the fetched `tag` that aren't in `HEAD`
+    fn commits_behind_tag(&self, tag: &str) -> DotManResult<usize>;
+
+    /// Rebases the current branch onto the fetched `<remote>/<branch>`. Conflicts are
+    /// handled as `resolution` says, if they can't be resolved the rebase is aborted
+    /// and [`GitError::Conflict`] is returned
+    fn rebase(&self, remote: &str, branch: &str, resolution: &Resolution) -> DotManResult<()>;
+
+    /// Merges the fetched `<remote>/<branch>` into the current branch, it's fast-forwarded
+    /// if there are no local commits. Conflicts are handled like in [`Git::rebase`]
+    fn merge(&self, remote: &str, branch: &str, resolution: &Resolution) -> DotManResult<()>;
+
+    /// Whether tracked files have uncommitted changes
+    fn is_dirty(&self) -> DotManResult<bool>;
 
+    /// Number of commits on the current branch that aren't on the remote branch
+    /// with the same name, 0 if HEAD is detached or the branch was never pushed
+    fn unpushed_commits(&self, remote: &str) -> DotManResult<usize>;
+
+    /// Stashes uncommitted changes to tracked files
+    fn stash(&mut self) -> DotManResult<()>;
+
+    /// Reapplies and drops the latest stash, it's kept if it doesn't apply cleanly
+    fn unstash(&mut self) -> DotManResult<()>;
+
+    /// Moves HEAD, the index and the working tree to `rev`, throwing away local changes
+    fn reset(&self, rev: &str) -> DotManResult<()>;
+
+    /// Limits the working tree to `paths`, everything is checked out if it's empty
+    fn set_sparse_paths(&mut self, paths: &[String]) -> DotManResult<()>;
+
+    /// Initializes, syncs and updates every submodule to the commit HEAD expects, recursively
+    fn update_submodules(&self) -> DotManResult<()>;
+
+    fn path(&self) -> String;
+}
+
+/// How conflicts during a rebase or merge are dealt with
+pub enum Resolution<'a> {
+    /// Give up straight away
+    Abort,
+    /// Take the local side of every conflicting hunk
+    KeepLocal,
+    /// Take the upstream side of every conflicting hunk
+    KeepUpstream,
+    /// Called with the conflicting files, which have conflict markers in the working
+    /// tree, and should remove the markers. Returns `false` to give up
+    Edit(&'a dyn Fn(&[String]) -> bool),
+}
+
+/// [`Git`] implemented in-process with libgit2, no `git` binary is needed
 pub struct GitWrapper {
     url: String,
     path: String,
+    repo: Repository,
+    /// Only these paths are in the working tree, libgit2 doesn't support sparse
+    /// checkouts so they are kept in git's `info/sparse-checkout` and applied by dotman
+    sparse: Vec<String>,
 }
 
 impl GitWrapper {
     pub fn open(url: &str, path: &str) -> DotManResult<GitWrapper> {
+        if !Path::new(path).exists() {
+            return Err(Error::IO(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{path} doesn't exist"),
+            )));
+        }
+
+        let repo = match Repository::open(path) {
+            Ok(r) => r,
+            Err(_) => {
+                return Err(Error::Git(GitError::NotARepository(format!(
+                    "{path} is not a git repo"
+                ))))
+            }
+        };
+
         let wrapper = Self {
             url: url.to_owned(),
             path: path.to_owned(),
+            sparse: read_sparse_paths(&repo),
+            repo,
         };
 
         match wrapper.get_remote_name() {
@@ -27,129 +143,1020 @@ impl GitWrapper {
         }
     }
 
-    pub fn clone(url: &str, path: &str) -> DotManResult<Self> {
-        let output = Command::new("git").args(["clone", url, path]).output()?;
+    /// Clones only the last `depth` commits if it's set, and only checks out
+    /// `sparse_paths` if there are any
+    pub fn clone(
+        url: &str,
+        path: &str,
+        branch: Option<&str>,
+        depth: Option<u32>,
+        sparse_paths: &[String],
+    ) -> DotManResult<Self> {
+        let mut options = fetch_options();
+        let mut command = "git clone".to_owned();
+        if let Some(depth) = depth {
+            options.depth(i32::try_from(depth).unwrap_or(i32::MAX));
+            command.push_str(&format!(" --depth {depth}"));
+        }
+
+        let mut checkout = CheckoutBuilder::new();
+        for path in sparse_paths {
+            checkout.path(path);
+        }
+
+        let mut builder = RepoBuilder::new();
+        builder.fetch_options(options).with_checkout(checkout);
+        if let Some(branch) = branch {
+            builder.branch(branch);
+        }
+
+        let repo = builder
+            .clone(url, Path::new(path))
+            .map_err(|e| handle_error(path, &format!("{command} {url}"), e))?;
 
         let wrapper = Self {
             url: url.to_owned(),
             path: path.to_owned(),
+            repo,
+            sparse: sparse_paths.to_vec(),
         };
 
-        if !output.status.success() {
-            return Err(wrapper.handle_error(output));
+        if !sparse_paths.is_empty() {
+            wrapper.run("git sparse-checkout set", || {
+                write_sparse_paths(&wrapper.repo, sparse_paths)?;
+                let head = wrapper.repo.head()?.peel(git2::ObjectType::Commit)?;
+                wrapper.sync_index(&head)
+            })?;
         }
 
         Ok(wrapper)
     }
 
-    pub fn current_branch_name(&self) -> DotManResult<String> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["branch", "--show-current"])
-            .output()?;
+    // `command` is the git command that does the same, it's kept in the error
+    fn run<T>(&self, command: &str, f: impl FnOnce() -> Result<T, git2::Error>) -> DotManResult<T> {
+        f().map_err(|e| handle_error(&self.path, command, e))
+    }
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+    fn remote_ref(&self, remote: &str, branch: &str) -> Result<Oid, git2::Error> {
+        let name = format!("refs/remotes/{remote}/{branch}");
+        match self.repo.refname_to_id(&name) {
+            Err(e) if e.code() == ErrorCode::NotFound => Err(missing_ref(&name)),
+            res => res,
         }
+    }
 
-        Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+    // Only tracked files count, untracked files are left alone just like `git rebase` does.
+    // Files left out by the sparse paths aren't deleted, just not checked out
+    fn dirty(&self) -> Result<bool, git2::Error> {
+        Ok(!self.changed_files()?.is_empty())
     }
 
-    pub fn checkout_branch(&self, name: &str) -> DotManResult<()> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["checkout", name])
-            .output()?;
+    fn changed_files(&self) -> Result<Vec<String>, git2::Error> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+        let checked_out = self.sparse_matcher()?;
+        let mut files = vec![];
+        for entry in self.repo.statuses(Some(&mut options))?.iter() {
+            let sparse = entry.status() == Status::WT_DELETED && !checked_out(entry.path_bytes());
+
+            if !entry.status().is_empty() && !sparse {
+                files.push(String::from_utf8_lossy(entry.path_bytes()).to_string());
+            }
+        }
+
+        Ok(files)
+    }
+
+    // Whether a path is checked out, they all are without sparse paths
+    fn sparse_matcher(&self) -> Result<impl Fn(&[u8]) -> bool, git2::Error> {
+        let everything = self.sparse.is_empty();
+        let pathspec = Pathspec::new(&self.sparse)?;
+
+        Ok(move |path: &[u8]| {
+            let path = String::from_utf8_lossy(path);
+            everything || pathspec.matches_path(Path::new(&*path), PathspecFlags::DEFAULT)
+        })
+    }
+
+    // Checkout limited to the sparse paths, if there are any
+    fn checkout_builder(&self) -> CheckoutBuilder<'static> {
+        let mut checkout = CheckoutBuilder::new();
+        for path in &self.sparse {
+            checkout.path(path);
+        }
+
+        checkout
+    }
+
+    // Checks out `object` without touching local changes
+    fn checkout(&self, object: &Object) -> Result<(), git2::Error> {
+        self.repo
+            .checkout_tree(object, Some(self.checkout_builder().safe()))?;
+        self.sync_index(object)
+    }
+
+    // A sparse checkout only updates the index for the paths it checked out, but the
+    // files outside of them still belong to `object`
+    fn sync_index(&self, object: &Object) -> Result<(), git2::Error> {
+        if self.sparse.is_empty() {
+            return Ok(());
+        }
+
+        let mut index = self.repo.index()?;
+        index.read_tree(&object.peel_to_tree()?)?;
+        self.skip_worktree(&mut index)?;
+        index.write()
+    }
+
+    // Marks the files outside of the sparse paths, and only those, so `git` knows they're
+    // left out on purpose. libgit2 drops the marks whenever it rewrites the index
+    fn skip_worktree(&self, index: &mut Index) -> Result<(), git2::Error> {
+        let checked_out = self.sparse_matcher()?;
+        let skip = IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+
+        let changed = index
+            .iter()
+            // Conflicts are left alone
+            .filter(|e| e.flags & STAGE_MASK == 0)
+            .filter(|e| (e.flags_extended & skip != 0) == checked_out(&e.path))
+            .collect::<Vec<_>>();
+        for mut entry in changed {
+            entry.flags_extended ^= skip;
+            index.add(&entry)?;
         }
 
         Ok(())
     }
 
-    pub fn get_remote_name(&self) -> DotManResult<String> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["remote", "-v"])
-            .output()?;
+    // Removes the files outside of the sparse paths from the working tree, for after
+    // libgit2 has checked out everything
+    fn apply_sparse_paths(&self) -> Result<(), git2::Error> {
+        let root = self.repo.workdir().unwrap_or(Path::new(&self.path));
+        let checked_out = self.sparse_matcher()?;
+        let mut index = self.repo.index()?;
+
+        for entry in index.iter() {
+            if checked_out(&entry.path) {
+                continue;
+            }
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+            let file = root.join(&*String::from_utf8_lossy(&entry.path));
+            if fs::remove_file(&file).is_ok() {
+                // Stops at the first directory that isn't empty
+                for dir in file.ancestors().skip(1).take_while(|d| *d != root) {
+                    if fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                }
+            }
         }
 
-        let output = String::from_utf8(output.stdout)?;
+        self.skip_worktree(&mut index)?;
+        index.write()
+    }
+
+    // Refuses to touch a working tree with uncommitted changes to tracked files
+    fn ensure_clean(&self) -> Result<(), git2::Error> {
+        if self.dirty()? {
+            return Err(git2::Error::new(
+                ErrorCode::Uncommitted,
+                ErrorClass::Repository,
+                "working tree has uncommitted changes",
+            ));
+        }
 
-        for line in output.lines() {
-            if line.contains(&self.url) {
-                return Ok(line
-                    .split('\t')
-                    .collect::<Vec<&str>>()
-                    .first()
-                    .unwrap()
-                    .to_string());
+        Ok(())
+    }
+
+    // Paths of every file with conflicts in the index
+    fn conflicts(&self) -> Result<Vec<String>, git2::Error> {
+        let mut index = self.repo.index()?;
+        index.read(false)?;
+
+        conflicting_files(&index)
+    }
+
+    // Writes the conflicting files in `index` to the working tree, with conflict markers
+    // labeled with `labels` where both sides changed them, for `edit` to resolve. What `edit`
+    // leaves behind is staged in `index` and the files are put back to how they are in HEAD
+    fn edit_conflicts(
+        &self,
+        index: &mut Index,
+        labels: (&str, &str),
+        edit: &dyn Fn(&[String]) -> bool,
+    ) -> Result<(Vec<String>, bool), git2::Error> {
+        let root = self
+            .repo
+            .workdir()
+            .unwrap_or(Path::new(&self.path))
+            .to_owned();
+        let mut files = vec![];
+
+        for conflict in index.conflicts()?.collect::<Vec<_>>() {
+            let conflict = conflict?;
+            let Some(side) = conflict.our.as_ref().or(conflict.their.as_ref()) else {
+                continue;
+            };
+
+            let content = match (&conflict.our, &conflict.their) {
+                (Some(ours), Some(theirs)) => {
+                    let ancestor = match &conflict.ancestor {
+                        Some(ancestor) => entry(&ancestor.path, ancestor.mode, ancestor.id),
+                        // Added on both sides, so everything conflicts
+                        None => entry(&ours.path, ours.mode, self.repo.blob(b"")?),
+                    };
+
+                    let mut options = MergeFileOptions::new();
+                    options.our_label(labels.0).their_label(labels.1);
+                    self.repo
+                        .merge_file_from_index(&ancestor, ours, theirs, Some(&mut options))?
+                        .content()
+                        .to_vec()
+                }
+                // Deleted on one side, the file is left as the other side has it
+                _ => self.repo.find_blob(side.id)?.content().to_vec(),
+            };
+
+            let path = String::from_utf8_lossy(&side.path).to_string();
+            if let Some(parent) = root.join(&path).parent() {
+                fs::create_dir_all(parent).map_err(io_error)?;
             }
+            fs::write(root.join(&path), content).map_err(io_error)?;
+            files.push((path, side.mode));
         }
 
-        Err(Error::RemoteNotFound(self.url.to_owned()))
+        let paths = files.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+        let resolved = edit(&paths) && self.stage_resolved(index, &root, &files)?;
+
+        self.restore(&paths)?;
+        Ok((paths, resolved))
     }
 
-    pub fn fetch(&self, remote: &str) -> DotManResult<()> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["fetch", remote, "master"])
-            .output()?;
+    // Stages `files` in `index` once they no longer contain conflict markers
+    fn stage_resolved(
+        &self,
+        index: &mut Index,
+        root: &Path,
+        files: &[(String, u32)],
+    ) -> Result<bool, git2::Error> {
+        for (path, mode) in files {
+            let content = match fs::read(root.join(path)) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    index.conflict_remove(Path::new(path))?;
+                    continue;
+                }
+                Err(e) => return Err(io_error(e)),
+            };
+
+            if String::from_utf8_lossy(&content)
+                .lines()
+                .any(|l| l.starts_with("<<<<<<<"))
+            {
+                return Ok(false);
+            }
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+            index.conflict_remove(Path::new(path))?;
+            index.add(&entry(path.as_bytes(), *mode, self.repo.blob(&content)?))?;
         }
 
+        Ok(!index.has_conflicts())
+    }
+
+    // Puts `files` back to how they are in HEAD
+    fn restore(&self, files: &[String]) -> Result<(), git2::Error> {
+        let root = self
+            .repo
+            .workdir()
+            .unwrap_or(Path::new(&self.path))
+            .to_owned();
+        let tree = self.repo.head()?.peel_to_tree()?;
+
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        let mut tracked = false;
+        for file in files {
+            if tree.get_path(Path::new(file)).is_ok() {
+                checkout.path(file);
+                tracked = true;
+            } else {
+                let _ = fs::remove_file(root.join(file));
+            }
+        }
+
+        // Without any paths everything would be checked out
+        if tracked {
+            self.repo.checkout_head(Some(&mut checkout))?;
+        }
+
+        Ok(())
+    }
+
+    // Checks out `target` and moves the current branch to it
+    fn move_head(&self, target: Oid, message: &str) -> Result<(), git2::Error> {
+        let object = self.repo.find_object(target, None)?;
+        self.checkout(&object)?;
+        self.repo.head()?.set_target(target, message)?;
+
         Ok(())
     }
 
-    pub fn rebase(&self) -> DotManResult<()> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["rebase", "master"])
-            .output()?;
+    // `<remote>/HEAD` is remembered if the remote had to be asked and `set_head` is true
+    fn find_default_branch(&self, remote: &str, set_head: bool) -> DotManResult<String> {
+        let head = format!("refs/remotes/{remote}/HEAD");
+        let prefix = format!("refs/remotes/{remote}/");
+
+        let target = self
+            .repo
+            .find_reference(&head)
+            .ok()
+            .and_then(|r| r.symbolic_target().map(|t| t.to_owned()));
+
+        if let Some(target) = target {
+            return Ok(target.strip_prefix(&prefix).unwrap_or(&target).to_owned());
+        }
+
+        // `<remote>/HEAD` is only set by clone, so ask the remote if it's missing
+        match self.remote_default_branch(remote) {
+            Ok(branch) if !branch.is_empty() => {
+                let target = format!("{prefix}{branch}");
+                if set_head && self.repo.find_reference(&target).is_ok() {
+                    self.run(&format!("git remote set-head {remote} {branch}"), || {
+                        self.repo
+                            .reference_symbolic(&head, &target, true, "dotman: set-head")
+                    })?;
+                }
+                Ok(branch)
+            }
+            _ => Ok("master".to_owned()),
+        }
+    }
+
+    fn remote_default_branch(&self, remote: &str) -> Result<String, git2::Error> {
+        let mut remote = self.repo.find_remote(remote)?;
+        let connection = remote.connect_auth(Direction::Fetch, Some(callbacks()), None)?;
+        let head = connection.default_branch()?;
+
+        Ok(head
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches("refs/heads/")
+            .to_owned())
+    }
+}
+
+impl Git for GitWrapper {
+    fn current_branch_name(&self) -> DotManResult<String> {
+        self.run("git branch --show-current", || {
+            let head = self.repo.find_reference("HEAD")?;
+
+            // Also works for a freshly initialized repository without commits
+            Ok(head
+                .symbolic_target()
+                .and_then(|t| t.strip_prefix("refs/heads/"))
+                .unwrap_or_default()
+                .to_owned())
+        })
+    }
+
+    fn head_commit(&self) -> DotManResult<String> {
+        self.run("git rev-parse HEAD", || {
+            Ok(self.repo.head()?.peel_to_commit()?.id().to_string())
+        })
+    }
+
+    fn checkout_branch(&self, name: &str) -> DotManResult<()> {
+        let remote = self.get_remote_name()?;
+
+        self.run(&format!("git checkout {name}"), || {
+            let branch = match self.repo.find_branch(name, BranchType::Local) {
+                Ok(b) => b,
+                Err(e) if e.code() == ErrorCode::NotFound => {
+                    let upstream_name = format!("{remote}/{name}");
+                    let upstream = self
+                        .repo
+                        .find_branch(&upstream_name, BranchType::Remote)
+                        .map_err(|_| missing_ref(&upstream_name))?;
+
+                    let mut branch =
+                        self.repo
+                            .branch(name, &upstream.get().peel_to_commit()?, false)?;
+                    branch.set_upstream(Some(&upstream_name))?;
+                    branch
+                }
+                Err(e) => return Err(e),
+            };
+
+            let reference = branch.into_reference();
+            let object = reference.peel(git2::ObjectType::Commit)?;
+            self.checkout(&object)?;
+            self.repo.set_head(reference.name().unwrap_or_default())?;
+
+            Ok(())
+        })
+    }
+
+    fn checkout_detached(&self, rev: &str) -> DotManResult<()> {
+        // A shallow clone might not have it, e.g. an older commit from the lockfile
+        if self.repo.is_shallow() && self.repo.revparse_single(rev).is_err() {
+            let remote = self.get_remote_name()?;
+            self.run(&format!("git fetch --unshallow {remote}"), || {
+                let mut options = fetch_options();
+                // libgit2's GIT_FETCH_DEPTH_UNSHALLOW
+                options.depth(i32::MAX);
+                self.repo
+                    .find_remote(&remote)?
+                    .fetch::<&str>(&[], Some(&mut options), None)
+            })?;
+        }
+
+        self.run(&format!("git checkout --detach {rev}"), || {
+            let commit = match self.repo.revparse_single(rev) {
+                Err(e) if e.code() == ErrorCode::NotFound => return Err(missing_ref(rev)),
+                res => res?.peel_to_commit()?,
+            };
+
+            self.checkout(commit.as_object())?;
+            self.repo.set_head_detached(commit.id())?;
+
+            Ok(())
+        })
+    }
+
+    fn get_remote_name(&self) -> DotManResult<String> {
+        let remotes = self.run("git remote", || self.repo.remotes())?;
+
+        for name in remotes.iter().flatten() {
+            let remote = self.run(&format!("git remote get-url {name}"), || {
+                self.repo.find_remote(name)
+            })?;
+            if remote.url().is_some_and(|u| same_repo(u, &self.url)) {
+                return Ok(name.to_owned());
+            }
+        }
+
+        Err(Error::RemoteNotFound(self.url.to_owned()))
+    }
+
+    fn default_branch(&self, remote: &str) -> DotManResult<String> {
+        self.find_default_branch(remote, true)
+    }
+
+    fn peek_default_branch(&self, remote: &str) -> DotManResult<String> {
+        self.find_default_branch(remote, false)
+    }
+
+    fn fetch_tag(&self, remote: &str, tag: &str) -> DotManResult<()> {
+        self.run(&format!("git fetch {remote} tag {tag}"), || {
+            let name = format!("refs/tags/{tag}");
+
+            // libgit2 never moves a tag it follows on its own, even with a forced refspec
+            let mut options = fetch_options();
+            options.download_tags(AutotagOption::None);
+            self.repo.find_remote(remote)?.fetch(
+                &[format!("+{name}:{name}")],
+                Some(&mut options),
+                None,
+            )?;
+
+            // libgit2 silently skips refspecs the remote doesn't have
+            if self.repo.find_reference(&name).is_err() {
+                return Err(missing_ref(&name));
+            }
+
+            Ok(())
+        })
+    }
+
+    fn fetch(&self, remote: &str, branch: &str) -> DotManResult<()> {
+        self.run(&format!("git fetch {remote} {branch}"), || {
+            self.repo.find_remote(remote)?.fetch(
+                &[format!(
+                    "+refs/heads/{branch}:refs/remotes/{remote}/{branch}"
+                )],
+                Some(&mut fetch_options()),
+                None,
+            )?;
+
+            // libgit2 silently skips refspecs the remote doesn't have
+            self.remote_ref(remote, branch)?;
+
+            Ok(())
+        })
+    }
+
+    fn commits_behind(&self, remote: &str, branch: &str) -> DotManResult<usize> {
+        self.run(
+            &format!("git rev-list --count HEAD..{remote}/{branch}"),
+            || {
+                let head = self.repo.head()?.peel_to_commit()?.id();
+                let upstream = self.remote_ref(remote, branch)?;
+                let (_, behind) = self.repo.graph_ahead_behind(head, upstream)?;
+
+                Ok(behind)
+            },
+        )
+    }
+
+    fn commits_behind_tag(&self, tag: &str) -> DotManResult<usize> {
+        self.run(&format!("git rev-list --count HEAD..{tag}"), || {
+            let head = self.repo.head()?.peel_to_commit()?.id();
+            let name = format!("refs/tags/{tag}");
+            let target = match self.repo.find_reference(&name) {
+                Err(e) if e.code() == ErrorCode::NotFound => return Err(missing_ref(&name)),
+                res => res?.peel_to_commit()?.id(),
+            };
+            let (_, behind) = self.repo.graph_ahead_behind(head, target)?;
+
+            Ok(behind)
+        })
+    }
+
+    fn rebase(&self, remote: &str, branch: &str, resolution: &Resolution) -> DotManResult<()> {
+        let conflicts = self.run(&format!("git rebase {remote}/{branch}"), || {
+            self.ensure_clean()?;
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+            let head = self.repo.head()?.peel_to_commit()?.id();
+            let upstream = self.remote_ref(remote, branch)?;
+
+            // Nothing new upstream
+            if head == upstream || self.repo.graph_descendant_of(head, upstream)? {
+                return Ok(vec![]);
+            }
+
+            // No local commits
+            if self.repo.graph_descendant_of(upstream, head)? {
+                self.move_head(upstream, "dotman: fast-forward")?;
+                return Ok(vec![]);
+            }
+
+            // While rebasing, "ours" is upstream and "theirs" is the local commit being applied
+            let mut merge_options = MergeOptions::new();
+            match resolution {
+                Resolution::KeepLocal => merge_options.file_favor(FileFavor::Theirs),
+                Resolution::KeepUpstream => merge_options.file_favor(FileFavor::Ours),
+                _ => &mut merge_options,
+            };
+
+            // In memory, libgit2 would otherwise take the files left out by the sparse
+            // paths as deleted and refuse to start
+            let mut options = RebaseOptions::new();
+            options.inmemory(true).merge_options(merge_options);
+
+            let local = self.repo.find_annotated_commit(head)?;
+            let onto = self.repo.find_annotated_commit(upstream)?;
+            let mut rebase =
+                self.repo
+                    .rebase(Some(&local), Some(&onto), None, Some(&mut options))?;
+
+            let mut target = upstream;
+            while let Some(operation) = rebase.next() {
+                let original = self.repo.find_commit(operation?.id())?;
+
+                let mut index = rebase.inmemory_index()?;
+                if index.has_conflicts() {
+                    let (conflicts, resolved) = match resolution {
+                        Resolution::Edit(edit) => {
+                            self.edit_conflicts(&mut index, ("upstream", "local"), *edit)?
+                        }
+                        _ => (conflicting_files(&index)?, false),
+                    };
+
+                    // Nothing has been touched yet
+                    if !resolved {
+                        rebase.abort()?;
+                        return Ok(conflicts);
+                    }
+                }
+
+                let committer = self
+                    .repo
+                    .signature()
+                    .unwrap_or_else(|_| original.committer().to_owned());
+
+                match rebase.commit(None, &committer, None) {
+                    Ok(id) => target = id,
+                    // Already upstream, nothing left to apply
+                    Err(e) if e.code() == ErrorCode::Applied => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            rebase.finish(None)?;
+            self.move_head(target, "dotman: rebase")?;
+            Ok(vec![])
+        })?;
+
+        if !conflicts.is_empty() {
+            return Err(Error::Git(GitError::Conflict(self.path.clone(), conflicts)));
         }
 
         Ok(())
     }
 
-    pub fn pull(&self, remote: &str) -> DotManResult<()> {
-        let output = Command::new("git")
-            .current_dir(Path::new(&self.path))
-            .args(["pull", remote, "master"])
-            .output()?;
+    fn merge(&self, remote: &str, branch: &str, resolution: &Resolution) -> DotManResult<()> {
+        let conflicts = self.run(&format!("git merge {remote}/{branch}"), || {
+            self.ensure_clean()?;
+
+            let head = self.repo.head()?.peel_to_commit()?;
+            let upstream = self.repo.find_commit(self.remote_ref(remote, branch)?)?;
 
-        if !output.status.success() {
-            return Err(self.handle_error(output));
+            // Nothing new upstream
+            if head.id() == upstream.id()
+                || self.repo.graph_descendant_of(head.id(), upstream.id())?
+            {
+                return Ok(vec![]);
+            }
+
+            // No local commits
+            if self.repo.graph_descendant_of(upstream.id(), head.id())? {
+                self.move_head(upstream.id(), "dotman: fast-forward")?;
+                return Ok(vec![]);
+            }
+
+            // While merging, "ours" is the local branch and "theirs" is upstream
+            let mut options = MergeOptions::new();
+            match resolution {
+                Resolution::KeepLocal => options.file_favor(FileFavor::Ours),
+                Resolution::KeepUpstream => options.file_favor(FileFavor::Theirs),
+                _ => &mut options,
+            };
+
+            // In memory for the same reason as the rebase
+            let mut index = self.repo.merge_commits(&head, &upstream, Some(&options))?;
+            if index.has_conflicts() {
+                let (conflicts, resolved) = match resolution {
+                    Resolution::Edit(edit) => {
+                        self.edit_conflicts(&mut index, ("local", "upstream"), *edit)?
+                    }
+                    _ => (conflicting_files(&index)?, false),
+                };
+
+                // Nothing has been touched yet
+                if !resolved {
+                    return Ok(conflicts);
+                }
+            }
+
+            let tree = self.repo.find_tree(index.write_tree_to(&self.repo)?)?;
+            let signature = self
+                .repo
+                .signature()
+                .unwrap_or_else(|_| head.committer().to_owned());
+            let merge = self.repo.commit(
+                None,
+                &signature,
+                &signature,
+                &format!("Merge remote-tracking branch '{remote}/{branch}'"),
+                &tree,
+                &[&head, &upstream],
+            )?;
+
+            self.move_head(merge, "dotman: merge")?;
+            Ok(vec![])
+        })?;
+
+        if !conflicts.is_empty() {
+            return Err(Error::Git(GitError::Conflict(self.path.clone(), conflicts)));
         }
 
         Ok(())
     }
 
-    pub fn path(&self) -> String {
+    fn is_dirty(&self) -> DotManResult<bool> {
+        self.run("git status", || self.dirty())
+    }
+
+    fn unpushed_commits(&self, remote: &str) -> DotManResult<usize> {
+        let branch = self.current_branch_name()?;
+        if branch.is_empty() {
+            return Ok(0);
+        }
+
+        self.run(
+            &format!("git rev-list --count {remote}/{branch}..HEAD"),
+            || {
+                let upstream = match self.remote_ref(remote, &branch) {
+                    Err(e) if e.code() == ErrorCode::NotFound => return Ok(0),
+                    res => res?,
+                };
+                let head = self.repo.head()?.peel_to_commit()?.id();
+                let (ahead, _) = self.repo.graph_ahead_behind(head, upstream)?;
+
+                Ok(ahead)
+            },
+        )
+    }
+
+    fn stash(&mut self) -> DotManResult<()> {
+        let stasher = self
+            .repo
+            .signature()
+            .or_else(|_| Signature::now("dotman", "dotman@localhost"));
+
+        let result = stasher.and_then(|s| {
+            if self.sparse.is_empty() {
+                return self.repo.stash_save(&s, "dotman: autostash", None);
+            }
+
+            // Files left out by the sparse paths would be stashed as deleted otherwise,
+            // libgit2 refuses paths without changes so only the changed files are given
+            let mut options = StashSaveOptions::new(s);
+            for path in self.changed_files()? {
+                options.pathspec(path);
+            }
+            let stash = self.repo.stash_save_ext(Some(&mut options))?;
+
+            // libgit2 checks out everything once the changes are stashed
+            self.apply_sparse_paths()?;
+            Ok(stash)
+        });
+        result
+            .map(|_| ())
+            .map_err(|e| handle_error(&self.path, "git stash", e))
+    }
+
+    fn unstash(&mut self) -> DotManResult<()> {
+        let mut options = StashApplyOptions::new();
+        options.checkout_options(self.checkout_builder());
+        self.repo
+            .stash_apply(0, Some(&mut options))
+            .map_err(|e| handle_error(&self.path, "git stash apply", e))?;
+        self.run("git stash apply", || self.apply_sparse_paths())?;
+
+        // Like `git stash pop`, the conflicts are left in the working tree and the stash is kept
+        let conflicts = self.run("git status", || self.conflicts())?;
+        if !conflicts.is_empty() {
+            return Err(Error::Git(GitError::Conflict(self.path.clone(), conflicts)));
+        }
+
+        self.repo
+            .stash_drop(0)
+            .map_err(|e| handle_error(&self.path, "git stash drop", e))
+    }
+
+    fn reset(&self, rev: &str) -> DotManResult<()> {
+        self.run(&format!("git reset --hard {rev}"), || {
+            let object = match self.repo.revparse_single(rev) {
+                Err(e) if e.code() == ErrorCode::NotFound => return Err(missing_ref(rev)),
+                res => res?,
+            };
+
+            self.repo
+                .reset(&object, ResetType::Hard, Some(&mut self.checkout_builder()))?;
+            self.apply_sparse_paths()
+        })
+    }
+
+    fn set_sparse_paths(&mut self, paths: &[String]) -> DotManResult<()> {
+        if self.sparse == paths {
+            return Ok(());
+        }
+
+        self.sparse = paths.to_vec();
+        self.run("git sparse-checkout set", || {
+            write_sparse_paths(&self.repo, paths)?;
+
+            // Brings back what's now included
+            let mut checkout = self.checkout_builder();
+            checkout.safe().recreate_missing(true);
+            self.repo.checkout_head(Some(&mut checkout))?;
+
+            // and removes what's now left out
+            self.apply_sparse_paths()
+        })
+    }
+
+    fn update_submodules(&self) -> DotManResult<()> {
+        self.run("git submodule update --init --recursive", || {
+            let checked_out = self.sparse_matcher()?;
+            update_submodules(&self.repo, &checked_out)
+        })
+    }
+
+    fn path(&self) -> String {
         self.path.clone()
     }
+}
 
-    fn handle_error(&self, output: Output) -> Error {
-        let output = match String::from_utf8(output.stderr) {
-            Ok(o) => o,
-            Err(e) => return Error::from(e),
-        };
+// Whether two urls point to the same repository, regardless of the protocol,
+// e.g. `git@github.com:owner/repo.git` and `https://github.com/owner/repo`
+fn same_repo(a: &str, b: &str) -> bool {
+    fn normalize(url: &str) -> String {
+        let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let url = url.rsplit_once('@').map(|(_, rest)| rest).unwrap_or(url);
+        let url = url.replacen(':', "/", 1);
 
-        if output.contains("not a git repository") {
-            Error::Git(GitError::NotARepository(format!(
-                "{} is not a git repo",
-                self.path
-            )))
-        } else {
-            Error::Git(GitError::Unknown(format!("{}: unknown error", self.path)))
+        url.trim_end_matches('/')
+            .trim_end_matches(".git")
+            .to_owned()
+    }
+
+    normalize(a) == normalize(b)
+}
+
+fn missing_ref(name: &str) -> git2::Error {
+    git2::Error::new(
+        ErrorCode::NotFound,
+        ErrorClass::Reference,
+        format!("'{name}' can't be found"),
+    )
+}
+
+// Submodules outside of the sparse paths aren't checked out either
+fn update_submodules(
+    repo: &Repository,
+    checked_out: &dyn Fn(&[u8]) -> bool,
+) -> Result<(), git2::Error> {
+    for mut submodule in repo.submodules()? {
+        if !checked_out(submodule.path().to_string_lossy().as_bytes()) {
+            continue;
         }
+
+        submodule.init(false)?;
+        // Picks up urls changed in `.gitmodules`, like `git submodule sync`
+        submodule.sync()?;
+
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch_options());
+        submodule.update(true, Some(&mut options))?;
+
+        update_submodules(&submodule.open()?, &|_| true)?;
+    }
+
+    Ok(())
+}
+
+// The conflict stage bits of an index entry's flags
+const STAGE_MASK: u16 = 0x3000;
+
+fn io_error(e: io::Error) -> git2::Error {
+    git2::Error::new(ErrorCode::GenericError, ErrorClass::Os, e.to_string())
+}
+
+// Paths of every file with conflicts in `index`
+fn conflicting_files(index: &Index) -> Result<Vec<String>, git2::Error> {
+    let mut files = vec![];
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        if let Some(entry) = entry {
+            files.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+
+    Ok(files)
+}
+
+// A resolved index entry, libgit2 fills in the rest
+fn entry(path: &[u8], mode: u32, id: Oid) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: path.to_vec(),
     }
 }
+
+fn sparse_checkout_file(repo: &Repository) -> PathBuf {
+    repo.path().join("info").join("sparse-checkout")
+}
+
+fn read_sparse_paths(repo: &Repository) -> Vec<String> {
+    let enabled = repo
+        .config()
+        .and_then(|c| c.get_bool("core.sparseCheckout"))
+        .unwrap_or(false);
+    if !enabled {
+        return vec![];
+    }
+
+    fs::read_to_string(sparse_checkout_file(repo))
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().trim_start_matches('/').to_owned())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect()
+}
+
+// Also lets `git` itself respect the sparse paths, anchored to the root of the repository
+fn write_sparse_paths(repo: &Repository, paths: &[String]) -> Result<(), git2::Error> {
+    let mut config = repo.config()?;
+    config.set_bool("core.sparseCheckout", !paths.is_empty())?;
+    config.set_bool("core.sparseCheckoutCone", false)?;
+
+    let file = sparse_checkout_file(repo);
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+
+    let patterns = paths
+        .iter()
+        .map(|p| format!("/{}\n", p.trim_start_matches('/')))
+        .collect::<String>();
+    fs::write(file, patterns).map_err(io_error)
+}
+
+fn handle_error(path: &str, command: &str, e: git2::Error) -> Error {
+    let failure = GitFailure {
+        path: path.to_owned(),
+        command: command.to_owned(),
+        status: format!("{:?}/{:?}", e.class(), e.code()),
+        message: e.message().to_owned(),
+    };
+
+    let network = matches!(
+        e.class(),
+        ErrorClass::Net | ErrorClass::Ssh | ErrorClass::Http
+    );
+    let message = e.message().to_lowercase();
+
+    let auth = e.code() == ErrorCode::Auth || (network && message.contains("authenticat"));
+    let unreachable = e.code() == ErrorCode::Timeout
+        || (network
+            && ["resolve", "connect", "unreachable", "timed out"]
+                .iter()
+                .any(|m| message.contains(m)));
+
+    Error::Git(match e.code() {
+        _ if auth => GitError::Authentication(failure),
+        _ if unreachable => GitError::HostUnreachable(failure),
+        ErrorCode::NotFastForward => GitError::Diverged(failure),
+        ErrorCode::Uncommitted
+        | ErrorCode::Conflict
+        | ErrorCode::Modified
+        | ErrorCode::Unmerged
+        | ErrorCode::IndexDirty => GitError::DirtyTree(failure),
+        ErrorCode::NotFound if e.class() == ErrorClass::Repository => {
+            GitError::NotARepository(format!("{path} is not a git repo"))
+        }
+        ErrorCode::NotFound => GitError::MissingRef(failure),
+        _ => GitError::Unknown(failure),
+    })
+}
+
+fn fetch_options() -> FetchOptions<'static> {
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks());
+    options
+}
+
+// libgit2 keeps asking for credentials until one works, so every kind is only
+// offered once: the ssh agent, then the usual key files, then git's credential helpers
+fn callbacks() -> RemoteCallbacks<'static> {
+    let mut tried_agent = false;
+    let mut tried_helper = false;
+    let mut keys = ssh_keys();
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        let username = username.unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !tried_agent {
+                tried_agent = true;
+                return Cred::ssh_key_from_agent(username);
+            }
+
+            if let Some(key) = keys.pop() {
+                return Cred::ssh_key(username, None, Path::new(&key), None);
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && !tried_helper {
+            tried_helper = true;
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, None);
+        }
+
+        Err(git2::Error::new(
+            ErrorCode::Auth,
+            ErrorClass::Net,
+            format!("no credentials were accepted by {url}"),
+        ))
+    });
+
+    callbacks
+}
+
+// Most preferred last, as they are popped
+fn ssh_keys() -> Vec<String> {
+    ["~/.ssh/id_rsa", "~/.ssh/id_ecdsa", "~/.ssh/id_ed25519"]
+        .iter()
+        .filter_map(|k| utils::expand("", k).ok())
+        .filter(|k| Path::new(k).exists())
+        .collect()
+}
@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    errors::{DotManResult, Error},
+};
+
+/// Exact commits of every source and every package, stored in `dotman.lock`
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    #[serde(default)]
+    pub sources: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedPackage {
+    pub url: String,
+    pub commit: String,
+}
+
+impl Lockfile {
+    pub fn load(config: &Config) -> DotManResult<Lockfile> {
+        let path = config.lockfile()?;
+        let content = match fs::read_to_string(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::LockfileNotFound(path))
+            }
+            Err(e) => return Err(e.into()),
+            Ok(c) => c,
+        };
+
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn load_or_default(config: &Config) -> DotManResult<Lockfile> {
+        match Self::load(config) {
+            Err(Error::LockfileNotFound(_)) => Ok(Lockfile::default()),
+            res => res,
+        }
+    }
+
+    pub fn save(&self, config: &Config) -> DotManResult<()> {
+        let path = config.lockfile()?;
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn commit(&self, name: &str) -> DotManResult<&str> {
+        match self.packages.get(name) {
+            Some(pkg) => Ok(&pkg.commit),
+            None => Err(Error::NotLocked(name.to_owned())),
+        }
+    }
+
+    pub fn lock(&mut self, name: &str, url: &str, commit: &str) {
+        self.packages.insert(
+            name.to_owned(),
+            LockedPackage {
+                url: url.to_owned(),
+                commit: commit.to_owned(),
+            },
+        );
+    }
+}
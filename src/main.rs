@@ -1,13 +1,19 @@
+use std::process::ExitCode;
+
 use clap::Parser;
-use cli::{Commands, InstallUpdateArgs};
+use cli::{Commands, InstallUpdateArgs, ListArgs, UninstallArgs};
 use config::Config;
-use repo::Repository;
+use errors::{DotManResult, Error};
+use lockfile::Lockfile;
+use repo::{Repository, SourceCheckout};
 
+mod backup;
 mod cli;
 mod commands;
 mod config;
 mod errors;
 mod gitactions;
+mod lockfile;
 mod package;
 mod print;
 mod remote;
@@ -15,101 +21,193 @@ mod repo;
 mod required_packages;
 mod script;
 mod source;
+mod state;
 mod utils;
 
-fn main() {
+fn main() -> ExitCode {
     let cli = cli::Cli::parse();
+    print::set_verbose(cli.verbose);
+
+    // Doesn't need a config, so it can run in a repository's CI
+    if let Commands::Validate { path } = &cli.command {
+        return exit(commands::validate(path));
+    }
 
-    let conf = match Config::load() {
+    let mut conf = match Config::load() {
         Ok(c) => c,
-        Err(e) => {
-            e.print_fatal();
-            panic!();
+        Err(e) => return fatal(e),
+    };
+
+    let (locked, https, dry_run) = match &cli.command {
+        Commands::Install {
+            locked,
+            https,
+            dry_run,
+            ..
+        }
+        | Commands::Update {
+            locked,
+            https,
+            dry_run,
+            ..
         }
+        | Commands::InstallEverything {
+            locked,
+            https,
+            dry_run,
+            ..
+        } => (*locked, *https, *dry_run),
+        _ => (false, false, false),
     };
 
-    let repo = match Repository::load(&conf) {
-        Ok(r) => r,
-        Err(e) => {
-            e.print_fatal();
-            panic!();
+    if https {
+        conf.force_https();
+    }
+
+    let lockfile = if locked {
+        match Lockfile::load(&conf) {
+            Ok(l) => Some(l),
+            Err(e) => return fatal(e),
         }
+    } else {
+        None
     };
 
-    match &cli.command {
+    // A dry run leaves the sources where they are, the plan says where they would be checked out
+    let checkout = match &lockfile {
+        _ if dry_run => SourceCheckout::AsIs,
+        Some(lockfile) => SourceCheckout::Locked(&lockfile.sources),
+        None => SourceCheckout::Branch,
+    };
+    let repo = match Repository::load(&conf, checkout) {
+        Ok(r) => r,
+        Err(e) => return fatal(e),
+    };
+
+    let result = match &cli.command {
         Commands::Install {
             packages,
             yes,
             force,
             no_scripts,
-        } => match commands::install_or_update(
+            locked,
+            dry_run,
+            jobs,
+            local_changes,
+            ..
+        }
+        | Commands::Update {
+            packages,
+            yes,
+            force,
+            no_scripts,
+            locked,
+            dry_run,
+            jobs,
+            local_changes,
+            ..
+        } => commands::install_or_update(
             &conf,
             &repo,
             InstallUpdateArgs {
                 yes,
                 force,
                 no_scripts,
+                locked,
+                dry_run,
+                jobs,
+                local_changes,
             },
             packages,
-        ) {
-            Ok(_) => {}
-            Err(e) => {
-                e.print_error();
-                return;
-            }
-        },
-        Commands::Update {
-            packages,
-            yes,
-            force,
-            no_scripts,
-        } => {
-            match commands::install_or_update(
-                &conf,
-                &repo,
-                InstallUpdateArgs {
-                    yes,
-                    force,
-                    no_scripts,
-                },
-                packages,
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    e.print_error();
-                    return;
-                }
-            }
-        }
+        ),
         Commands::InstallEverything {
             yes,
             force,
             no_scripts,
+            locked,
+            dry_run,
+            jobs,
+            local_changes,
+            ..
         } => {
             let mut all_packages = vec![];
             for pkg in &repo.packages {
                 all_packages.push(pkg.name.clone());
             }
 
-            match commands::install_or_update(
+            commands::install_or_update(
                 &conf,
                 &repo,
                 InstallUpdateArgs {
                     yes,
                     force,
                     no_scripts,
+                    locked,
+                    dry_run,
+                    jobs,
+                    local_changes,
                 },
                 &all_packages,
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    e.print_error();
-                    return;
-                }
-            }
+            )
+        }
+        Commands::Uninstall {
+            packages,
+            yes,
+            cascade,
+            no_scripts,
+        } => commands::uninstall(
+            &repo,
+            UninstallArgs {
+                yes,
+                cascade,
+                no_scripts,
+            },
+            packages,
+        ),
+        Commands::List {
+            installed,
+            available,
+            explicit,
+            deps,
+            outdated,
+        } => commands::list(
+            &conf,
+            &repo,
+            ListArgs {
+                installed,
+                available,
+                explicit,
+                deps,
+                outdated,
+            },
+        ),
+        Commands::Why { package, from } => commands::why(&repo, package, from),
+        Commands::Rdeps { package } => commands::rdeps(&repo, package),
+        Commands::Graph { packages, format } => commands::graph(&repo, packages, format),
+        Commands::Restore { package, force } => commands::restore(package, force),
+        Commands::Validate { .. } => unreachable!(),
+        Commands::Search { query } => {
+            commands::search(&repo, query);
+            Ok(())
+        }
+        Commands::Inspect { package } => commands::inspect(&repo, package),
+        Commands::Script { function, packages } => commands::script(&repo, packages, function),
+    };
+
+    exit(result)
+}
+
+fn exit(result: DotManResult<()>) -> ExitCode {
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.print_error();
+            ExitCode::from(e.exit_code())
         }
-        Commands::Search { query } => commands::search(&repo, &query),
-        Commands::Inspect { package } => commands::inspect(&repo, &package),
-        Commands::Script { function, packages } => commands::script(&repo, &packages, &function),
     }
 }
+
+fn fatal(e: Error) -> ExitCode {
+    e.print_fatal();
+    ExitCode::from(e.exit_code())
+}
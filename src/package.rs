@@ -1,7 +1,14 @@
+use std::fmt;
+
 use colored::Colorize;
 use serde::Deserialize;
 
-use crate::{errors::DotManResult, print, utils};
+use crate::{
+    config::Config,
+    errors::DotManResult,
+    gitactions::{Git, GitWrapper},
+    print, utils,
+};
 
 use super::remote::Remote;
 
@@ -13,6 +20,39 @@ pub struct Package {
     pub repo: Remote,
     pub install_path: String,
     pub dependencies: Vec<String>,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    /// Number of commits to clone, the whole history if it isn't set
+    pub clone_depth: Option<u32>,
+    /// Only these files and directories are checked out
+    #[serde(default)]
+    pub sparse_paths: Vec<String>,
+    /// Whether to check out the repository's submodules
+    #[serde(default)]
+    pub submodules: bool,
+
+    /// Name of the source the package was loaded from
+    #[serde(skip)]
+    pub source: String,
+}
+
+/// A ref a package is pinned to, only one of `branch`, `tag` and `rev` may be set
+#[derive(Debug, Clone)]
+pub enum Pin {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::Branch(branch) => write!(f, "branch '{branch}'"),
+            Pin::Tag(tag) => write!(f, "tag '{tag}'"),
+            Pin::Rev(rev) => write!(f, "rev '{rev}'"),
+        }
+    }
 }
 
 impl Package {
@@ -24,15 +64,84 @@ impl Package {
         utils::expand("", &self.install_path)
     }
 
+    /// Installed packages are a dotman repo at `install_path`, the state file only
+    /// knows why and when they were installed
+    pub fn is_installed(&self) -> DotManResult<bool> {
+        Ok(GitWrapper::open(&self.url(), &self.install_path()?).is_ok())
+    }
+
+    /// The branch to update from, the package's own `branch`, the config's `branch`
+    /// or the default branch of the remote, in that order
+    pub fn branch(&self, conf: &Config, git: &dyn Git, remote: &str) -> DotManResult<String> {
+        match self.branch.as_ref().or(conf.branch.as_ref()) {
+            Some(branch) => Ok(branch.clone()),
+            None => git.default_branch(remote),
+        }
+    }
+
+    /// [`Package::branch`] that leaves the repository untouched, for `--dry-run`
+    pub fn peek_branch(&self, conf: &Config, git: &dyn Git, remote: &str) -> DotManResult<String> {
+        match self.branch.as_ref().or(conf.branch.as_ref()) {
+            Some(branch) => Ok(branch.clone()),
+            None => git.peek_default_branch(remote),
+        }
+    }
+
+    /// The package's own `clone-depth`, otherwise the config's
+    pub fn clone_depth(&self, conf: &Config) -> Option<u32> {
+        self.clone_depth.or(conf.git.clone_depth)
+    }
+
+    /// `sparse-paths`, the package's script, which is always needed, and `.gitmodules`
+    /// if the submodules are used
+    pub fn sparse_paths(&self) -> Vec<String> {
+        if self.sparse_paths.is_empty() {
+            return vec![];
+        }
+
+        let mut paths = self.sparse_paths.clone();
+        paths.push(".dotman.lua".to_owned());
+        if self.submodules {
+            paths.push(".gitmodules".to_owned());
+        }
+        paths
+    }
+
+    pub fn pin(&self) -> Option<Pin> {
+        if let Some(branch) = &self.branch {
+            Some(Pin::Branch(branch.clone()))
+        } else if let Some(tag) = &self.tag {
+            Some(Pin::Tag(tag.clone()))
+        } else {
+            self.rev.as_ref().map(|rev| Pin::Rev(rev.clone()))
+        }
+    }
+
+    /// The tag or commit to stay on instead of following a branch
+    pub fn detached_ref(&self) -> Option<String> {
+        match self.pin() {
+            Some(Pin::Tag(r) | Pin::Rev(r)) => Some(r),
+            _ => None,
+        }
+    }
+
     pub fn pprint(&self) {
+        let pin = match self.pin() {
+            Some(pin) => format!("\n  {}: {}", "Pinned".bold(), pin.to_string().italic()),
+            None => String::new(),
+        };
+
         print::info(&format!(
-            "{} - {}\n  {}: {}\n  {}: {}",
+            "{} - {}\n  {}: {}\n  {}: {}\n  {}: {}{}",
             self.name.blue().bold(),
             self.description.italic(),
+            "Source".bold(),
+            self.source.italic(),
             "Url".bold(),
             self.repo.url().italic(),
             "Install Path".bold(),
-            self.install_path.italic()
+            self.install_path.italic(),
+            pin
         ))
     }
 }
@@ -43,6 +152,10 @@ pub struct Collection {
     pub name: String,
     pub description: String,
     pub packages: Vec<String>,
+
+    /// Name of the source the collection was loaded from
+    #[serde(skip)]
+    pub source: String,
 }
 
 impl Collection {
@@ -91,9 +204,41 @@ impl PackageFile {
         self.package.as_ref().unwrap().clone()
     }
 
+    /// Name of the package or collection in the file
+    pub fn name(&self) -> String {
+        match (&self.package, &self.collection) {
+            (Some(pkg), _) => pkg.name.clone(),
+            (None, Some(col)) => col.name.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Prefixes the name of the package or collection with `namespace/`
+    pub fn namespace(&mut self, namespace: &str) {
+        if let Some(pkg) = &mut self.package {
+            pkg.name = format!("{namespace}/{}", pkg.name);
+        }
+
+        if let Some(col) = &mut self.collection {
+            col.name = format!("{namespace}/{}", col.name);
+        }
+    }
+
     pub fn valid(&self) -> bool {
         let both = !(self.is_collection() && self.is_package());
         let neither = !(!self.is_collection() && !self.is_package());
-        both && neither
+        both && neither && self.valid_pin()
+    }
+
+    fn valid_pin(&self) -> bool {
+        let Some(pkg) = &self.package else {
+            return true;
+        };
+
+        [&pkg.branch, &pkg.tag, &pkg.rev]
+            .iter()
+            .filter(|r| r.is_some())
+            .count()
+            <= 1
     }
 }
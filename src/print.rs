@@ -1,5 +1,18 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use colored::{ColoredString, Colorize};
 
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Makes errors include everything that is known about them, set by `--verbose`
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
 fn format(t: ColoredString, message: &str) {
     println!("{}: {}", t, message);
 }
@@ -48,8 +61,4 @@ impl Printer {
     pub fn error(&self, message: &str) {
         error(&format!("{}: {}", self.package.bold().italic(), message))
     }
-
-    pub fn fatal(&self, message: &str) {
-        fatal(&format!("{}: {}", self.package.bold().italic(), message))
-    }
 }
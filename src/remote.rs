@@ -1,5 +1,7 @@
 use serde::Deserialize;
 
+use crate::utils;
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(tag = "host")]
 #[serde(rename_all = "kebab-case")]
@@ -7,24 +9,126 @@ pub enum Remote {
     Github {
         owner: String,
         repo: String,
+        protocol: Option<Protocol>,
     },
     Gitlab {
         owner: String,
         repo: String,
+        protocol: Option<Protocol>,
     },
-    Custom {
+    Codeberg {
         owner: String,
         repo: String,
+        protocol: Option<Protocol>,
+    },
+    Sourcehut {
+        owner: String,
+        repo: String,
+        protocol: Option<Protocol>,
+    },
+    /// A self-hosted Gitea or Forgejo, `instance` is its domain, e.g. `git.example.com`
+    Gitea {
+        instance: String,
+        owner: String,
+        repo: String,
+        protocol: Option<Protocol>,
+    },
+    /// `url` is the host when `owner` and `repo` are set, otherwise the whole url
+    Custom {
+        owner: Option<String>,
+        repo: Option<String>,
         url: String,
+        protocol: Option<Protocol>,
     },
+    /// Cloned from `url` as is
+    Url { url: String },
+    /// A local directory or bare repository
+    Path { path: String },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    Ssh,
+    Https,
+}
+
+impl Protocol {
+    pub fn default() -> Protocol {
+        Protocol::Ssh
+    }
 }
 
 impl Remote {
     pub fn url(&self) -> String {
+        let (host, owner, repo) = match &self {
+            Remote::Github { owner, repo, .. } => ("github.com", owner.as_str(), repo),
+            Remote::Gitlab { owner, repo, .. } => ("gitlab.com", owner.as_str(), repo),
+            Remote::Codeberg { owner, repo, .. } => ("codeberg.org", owner.as_str(), repo),
+            Remote::Sourcehut { owner, repo, .. } => {
+                // sourcehut puts a `~` in front of every user and doesn't use the `.git` suffix
+                let owner = format!("~{}", owner.trim_start_matches('~'));
+                return match self.protocol().unwrap_or(Protocol::default()) {
+                    Protocol::Ssh => format!("git@git.sr.ht:{owner}/{repo}"),
+                    Protocol::Https => format!("https://git.sr.ht/{owner}/{repo}"),
+                };
+            }
+            Remote::Gitea {
+                instance,
+                owner,
+                repo,
+                ..
+            } => {
+                let instance = instance
+                    .split_once("://")
+                    .map_or(instance.as_str(), |(_, i)| i);
+                (instance.trim_end_matches('/'), owner.as_str(), repo)
+            }
+            Remote::Custom {
+                owner: Some(owner),
+                repo: Some(repo),
+                url,
+                ..
+            } => (url.as_str(), owner.as_str(), repo),
+            Remote::Custom { url, .. } | Remote::Url { url } => return url.clone(),
+            Remote::Path { path } => return utils::expand("", path).unwrap_or(path.clone()),
+        };
+
+        match self.protocol().unwrap_or(Protocol::default()) {
+            Protocol::Ssh => format!("git@{host}:{owner}/{repo}.git"),
+            Protocol::Https => format!("https://{host}/{owner}/{repo}.git"),
+        }
+    }
+
+    /// A directory or `file://` url rather than a server
+    pub fn is_local(&self) -> bool {
+        let url = self.url();
+        url.starts_with("file://") || !url.contains(':')
+    }
+
+    /// The protocol set in the package or source file, if any
+    pub fn protocol(&self) -> Option<Protocol> {
         match &self {
-            Remote::Github { owner, repo } => format!("git@github.com:{owner}/{repo}.git"),
-            Remote::Gitlab { owner, repo } => format!("git@gitlab.com:{owner}/{repo}.git"),
-            Remote::Custom { owner, repo, url } => format!("git@{url}:{owner}/{repo}.git"),
+            Remote::Github { protocol, .. }
+            | Remote::Gitlab { protocol, .. }
+            | Remote::Codeberg { protocol, .. }
+            | Remote::Sourcehut { protocol, .. }
+            | Remote::Gitea { protocol, .. }
+            | Remote::Custom { protocol, .. } => *protocol,
+            Remote::Url { .. } | Remote::Path { .. } => None,
+        }
+    }
+
+    /// Does nothing for remotes that are used as is
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        match self {
+            Remote::Github { protocol: p, .. }
+            | Remote::Gitlab { protocol: p, .. }
+            | Remote::Codeberg { protocol: p, .. }
+            | Remote::Sourcehut { protocol: p, .. }
+            | Remote::Gitea { protocol: p, .. }
+            | Remote::Custom { protocol: p, .. } => *p = Some(protocol),
+            Remote::Url { .. } | Remote::Path { .. } => {}
         }
     }
 }
@@ -1,65 +1,220 @@
-use std::fs;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
 
 use crate::{
     config::Config,
     errors::{DotManResult, Error, GitError},
-    gitactions::GitWrapper,
+    gitactions::{Git, GitWrapper},
     package::{Collection, Package, PackageFile},
+    source::Source,
 };
 
+/// Settings for a whole source, read from `.dotman.toml` in its root
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RepositorySettings {
+    /// Prefix package and collection names with the directory they are in, e.g. `wm/hyprland`
+    #[serde(default)]
+    pub namespaced: bool,
+}
+
+impl RepositorySettings {
+    pub fn load(root: &Path) -> DotManResult<RepositorySettings> {
+        match fs::read_to_string(root.join(".dotman.toml")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(RepositorySettings::default()),
+            Err(e) => Err(e.into()),
+            Ok(content) => match toml::from_str(&content) {
+                Ok(settings) => Ok(settings),
+                Err(e) => Err(Error::MalformattedPackageWithError(
+                    ".dotman.toml".to_owned(),
+                    e,
+                )),
+            },
+        }
+    }
+}
+
+/// Where every source is checked out before its packages are read
+pub enum SourceCheckout<'a> {
+    /// At the commit of its name, sources that aren't in there stay on their branch
+    Locked(&'a BTreeMap<String, String>),
+    /// On its branch, a source left on a detached HEAD by a locked run is switched back
+    Branch,
+    /// Wherever it is, for `--dry-run`
+    AsIs,
+}
+
 #[derive(Debug)]
 pub struct Repository {
+    pub sources: Vec<Source>,
+    /// The checked out commit of every source
+    pub commits: BTreeMap<String, String>,
     pub packages: Vec<Package>,
     pub collections: Vec<Collection>,
+
+    // Packages and collections hidden by one with the same name from a source
+    // with higher priority, these can only be reached as `source/name`
+    shadowed_packages: Vec<Package>,
+    shadowed_collections: Vec<Collection>,
 }
 
 impl Repository {
-    pub fn load(config: &Config) -> DotManResult<Repository> {
+    /// Loads every source after checking it out as `checkout` says
+    pub fn load(config: &Config, checkout: SourceCheckout) -> DotManResult<Repository> {
+        let mut repo = Repository {
+            sources: config.sources()?,
+            commits: BTreeMap::new(),
+            packages: vec![],
+            collections: vec![],
+            shadowed_packages: vec![],
+            shadowed_collections: vec![],
+        };
+
+        // Sources are sorted by priority, so the first definition of a name wins
+        for source in repo.sources.clone() {
+            let (commit, files) = Self::load_source(config, &source, &checkout)?;
+            repo.commits.insert(source.name.clone(), commit);
+
+            for file in files {
+                if file.is_package() {
+                    let mut package = file.package();
+                    package.source = source.name.clone();
+                    package.repo.set_protocol(config.protocol(&package.repo));
+
+                    if repo.packages.iter().any(|p| p.name == package.name) {
+                        repo.shadowed_packages.push(package);
+                    } else {
+                        repo.packages.push(package);
+                    }
+                }
+
+                if file.is_collection() {
+                    let mut collection = file.collection();
+                    collection.source = source.name.clone();
+
+                    if repo.collections.iter().any(|c| c.name == collection.name) {
+                        repo.shadowed_collections.push(collection);
+                    } else {
+                        repo.collections.push(collection);
+                    }
+                }
+            }
+        }
+
+        Ok(repo)
+    }
+
+    fn load_source(
+        config: &Config,
+        source: &Source,
+        checkout: &SourceCheckout,
+    ) -> DotManResult<(String, Vec<PackageFile>)> {
         // TODO: Implement --force
-        let git = match GitWrapper::open(&config.url(), &config.path()?) {
+        let git = match GitWrapper::open(&source.url(), &source.path()?) {
             Ok(wrapper) => wrapper,
             Err(Error::Git(GitError::NotARepository(_))) => {
-                match GitWrapper::clone(&config.url(), &config.path()?) {
+                match GitWrapper::clone(
+                    &source.url(),
+                    &source.path()?,
+                    config.branch.as_deref(),
+                    None,
+                    &[],
+                ) {
                     Ok(wrapper) => wrapper,
                     Err(e) => return Err(e),
                 }
             }
+            Err(Error::IO(e)) if e.kind() == io::ErrorKind::NotFound => GitWrapper::clone(
+                &source.url(),
+                &source.path()?,
+                config.branch.as_deref(),
+                None,
+                &[],
+            )?,
             Err(e) => return Err(e),
         };
 
-        let dir = std::fs::read_dir(git.path())?;
-        let mut packages = vec![];
-        let mut collections = vec![];
-
-        for path in dir {
-            let path = path?;
-            let file_name = path.file_name();
-            let name = file_name.to_string_lossy();
-            // TODO: Only get filename without rest of the path
-            if name.ends_with(".toml") {
-                let content = fs::read_to_string(path.path())?;
-                let file: PackageFile = match toml::from_str(&content) {
-                    Ok(f) => f,
-                    Err(e) => return Err(Error::MalformattedPackageWithError(name.to_string(), e)),
-                };
-                if !file.valid() {
-                    return Err(Error::MalformattedPackage(format!("{name}")));
-                }
+        let commit = match checkout {
+            SourceCheckout::Locked(commits) => commits.get(&source.name),
+            _ => None,
+        };
+        let remote = git.get_remote_name()?;
+        let branch = || match &config.branch {
+            Some(branch) => Ok(branch.clone()),
+            None => git.default_branch(&remote),
+        };
 
-                if file.is_package() {
-                    packages.push(file.package());
-                }
+        if let Some(commit) = commit {
+            if git.head_commit()? != *commit {
+                git.fetch(&remote, &branch()?)?;
+                git.checkout_detached(commit)?;
+            }
+        } else if !matches!(checkout, SourceCheckout::AsIs) && git.current_branch_name()?.is_empty()
+        {
+            git.checkout_branch(&branch()?)?;
+        }
 
-                if file.is_collection() {
-                    collections.push(file.collection());
-                }
+        let root = PathBuf::from(git.path());
+        let settings = RepositorySettings::load(&root)?;
+
+        let mut paths = vec![];
+        find_package_files(&root, &mut paths)?;
+
+        // Name -> file it was defined in, to report duplicates
+        let mut names: BTreeMap<String, String> = BTreeMap::new();
+        let mut files = vec![];
+
+        for path in paths {
+            let relative = path.strip_prefix(&root).unwrap_or(&path);
+            let name = relative.to_string_lossy().to_string();
+
+            let content = fs::read_to_string(&path)?;
+            let file = parse_package_file(&content, relative, &settings)?;
+
+            let defined = file.name();
+            if let Some(other) = names.get(&defined) {
+                return Err(Error::DuplicateName(defined, other.clone(), name));
+            }
+            names.insert(defined, name);
+
+            files.push(file);
+        }
+
+        Ok((git.head_commit()?, files))
+    }
+
+    /// A repository of already parsed files without any sources, names aren't
+    /// checked for duplicates
+    pub fn from_files(files: Vec<PackageFile>) -> Repository {
+        let mut repo = Repository {
+            sources: vec![],
+            commits: BTreeMap::new(),
+            packages: vec![],
+            collections: vec![],
+            shadowed_packages: vec![],
+            shadowed_collections: vec![],
+        };
+
+        for file in files {
+            if file.is_package() {
+                repo.packages.push(file.package());
+            }
+
+            if file.is_collection() {
+                repo.collections.push(file.collection());
             }
         }
 
-        return Ok(Repository {
-            packages,
-            collections,
-        });
+        repo
+    }
+
+    pub fn source(&self, name: &str) -> Option<&Source> {
+        self.sources.iter().find(|s| s.name == name)
     }
 
     // This should only be called when the package is known to exist
@@ -69,6 +224,7 @@ impl Repository {
             .expect(&format!("{name} to exist"));
     }
 
+    /// Finds a package by name, or by `source/name` to pick it from a specific source
     pub fn try_get_package_from_string(&self, name: &str) -> Option<Package> {
         for pkg in &self.packages {
             if pkg.name == name {
@@ -76,6 +232,94 @@ impl Repository {
             }
         }
 
-        return None;
+        let (source, name) = name.split_once('/')?;
+        self.packages
+            .iter()
+            .chain(&self.shadowed_packages)
+            .find(|p| p.source == source && p.name == name)
+            .cloned()
+    }
+
+    /// Every package, including the ones only reachable as `source/name`
+    pub fn all_packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages.iter().chain(&self.shadowed_packages)
     }
+
+    /// What `pkg` is recorded as in the state file and the lockfile, `source/name` if it's
+    /// hidden by a package with the same name from a source with higher priority
+    pub fn package_id(&self, pkg: &Package) -> String {
+        if self
+            .packages
+            .iter()
+            .any(|p| p.name == pkg.name && p.source == pkg.source)
+        {
+            pkg.name.clone()
+        } else {
+            format!("{}/{}", pkg.source, pkg.name)
+        }
+    }
+
+    /// Finds a collection by name, or by `source/name` to pick it from a specific source
+    pub fn try_get_collection_from_string(&self, name: &str) -> Option<Collection> {
+        for col in &self.collections {
+            if col.name == name {
+                return Some(col.clone());
+            }
+        }
+
+        let (source, name) = name.split_once('/')?;
+        self.collections
+            .iter()
+            .chain(&self.shadowed_collections)
+            .find(|c| c.source == source && c.name == name)
+            .cloned()
+    }
+}
+
+/// Parses a package file, `relative` is its path from the root of the repository
+pub fn parse_package_file(
+    content: &str,
+    relative: &Path,
+    settings: &RepositorySettings,
+) -> DotManResult<PackageFile> {
+    let name = relative.to_string_lossy().to_string();
+
+    let mut file: PackageFile = match toml::from_str(content) {
+        Ok(f) => f,
+        Err(e) => return Err(Error::MalformattedPackageWithError(name, e)),
+    };
+    if !file.valid() {
+        return Err(Error::MalformattedPackage(name));
+    }
+
+    if settings.namespaced {
+        if let Some(namespace) = relative.parent().filter(|p| *p != Path::new("")) {
+            file.namespace(&namespace.to_string_lossy());
+        }
+    }
+
+    Ok(file)
+}
+
+/// Every `*.toml` file under `dir`, hidden files and directories like `.git` and
+/// `.dotman.toml` are skipped
+pub fn find_package_files(dir: &Path, files: &mut Vec<PathBuf>) -> DotManResult<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let file_name = entry.file_name();
+        let name = file_name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            find_package_files(&entry.path(), files)?;
+        } else if name.ends_with(".toml") {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(())
 }
@@ -1,96 +1,151 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 
 use crate::{
     errors::{DotManResult, Error},
     repo::Repository,
 };
 
-// The amout of cloing in this file is sad... but performace isn't an issue
+/// Resolves packages and collections into every package they require, ordered
+/// so that a package always comes after its dependencies
 pub fn gather_required_packages(
     pcs: &Vec<String>, // Packages and Collections
     repo: &Repository,
-) -> DotManResult<HashSet<String>> {
-    let mut visited: Vec<String> = vec![];
-
-    let mut result = HashSet::new();
+) -> DotManResult<Vec<String>> {
+    let mut stack = vec![];
+    let mut done = HashSet::new();
+    let mut result = vec![];
 
     for pc in pcs {
-        visited.push(pc.clone());
+        visit(pc, repo, &mut stack, &mut done, &mut result)?;
+    }
+
+    Ok(result)
+}
 
-        let packages = packages(pc, repo)?;
+/// The packages that were asked for directly, either by name or through a collection
+pub fn explicit_packages(pcs: &Vec<String>, repo: &Repository) -> HashSet<String> {
+    let mut result = HashSet::new();
 
-        for p in packages {
-            result.insert(p);
+    for pc in pcs {
+        match repo.try_get_collection_from_string(pc) {
+            Some(c) => result.extend(c.packages.iter().cloned()),
+            None => {
+                result.insert(pc.clone());
+            }
         }
     }
 
-    let pkgs = result.clone();
-    add_recursive(&pkgs, repo, &mut visited, &mut result)?;
-
-    Ok(result)
+    result
 }
 
-fn add_recursive(
-    pkgs: &HashSet<String>,
+// Depth first search, a package is only pushed to `result` once all of its
+// dependencies have been. `stack` holds the current path and is used to find cycles
+fn visit(
+    name: &str,
     repo: &Repository,
-    visited: &mut Vec<String>,
-    res: &mut HashSet<String>,
+    stack: &mut Vec<String>,
+    done: &mut HashSet<String>,
+    result: &mut Vec<String>,
 ) -> DotManResult<()> {
-    let visited_copy = &visited[..];
+    if done.contains(name) {
+        return Ok(());
+    }
 
-    let unique: Vec<String> = pkgs
-        .iter()
-        .filter(move |p| {
-            for v in visited_copy {
-                // This is cursed
-                if **p == *v {
-                    return false;
-                }
-            }
-            true
-        })
-        .map(|p| p.clone())
-        .collect();
+    if let Some(pos) = stack.iter().position(|n| n == name) {
+        let mut cycle = stack[pos..].to_vec();
+        cycle.push(name.to_owned());
+        return Err(Error::DependencyCycle(cycle));
+    }
 
-    if unique.len() == 0 {
-        return Ok(());
+    stack.push(name.to_owned());
+
+    for c in children(name, repo)? {
+        visit(&c, repo, stack, done, result)?;
     }
 
-    for p in unique {
-        let pack = packages(&p, repo)?;
-        for p in pack {
-            visited.push(p.clone());
-            res.insert(p.to_string());
-        }
+    if repo.try_get_package_from_string(name).is_some() {
+        result.push(name.to_owned());
     }
 
-    add_recursive(pkgs, repo, visited, res)
+    stack.pop();
+    done.insert(name.to_owned());
+
+    Ok(())
 }
 
-fn packages(package: &str, repo: &Repository) -> DotManResult<HashSet<String>> {
-    for c in &repo.collections[..] {
-        if c.name == package {
-            let p = c.packages.clone();
-            return Ok(str_vec_to_str_hash(&p));
-        }
+/// The members of a collection or the dependencies of a package
+pub fn children(name: &str, repo: &Repository) -> DotManResult<Vec<String>> {
+    if let Some(c) = repo.try_get_collection_from_string(name) {
+        return Ok(c.packages);
     }
 
-    for p in &repo.packages[..] {
-        if p.name == package {
-            let mut deps = p.dependencies.clone();
-            deps.push(p.name.clone());
-            return Ok(str_vec_to_str_hash(&deps));
+    if let Some(p) = repo.try_get_package_from_string(name) {
+        return Ok(p.dependencies);
+    }
+
+    Err(Error::UnknownPackage(name.to_owned()))
+}
+
+/// The shortest chain of collections and dependencies from `from` to `to`,
+/// including both ends
+pub fn dependency_path(
+    from: &str,
+    to: &str,
+    repo: &Repository,
+) -> DotManResult<Option<Vec<String>>> {
+    let mut queue = VecDeque::from([vec![from.to_owned()]]);
+    let mut seen = HashSet::from([from.to_owned()]);
+
+    while let Some(path) = queue.pop_front() {
+        let last = path.last().unwrap();
+        if last == to {
+            return Ok(Some(path));
+        }
+
+        for c in children(last, repo)? {
+            if seen.insert(c.clone()) {
+                let mut next = path.clone();
+                next.push(c);
+                queue.push_back(next);
+            }
         }
     }
 
-    Err(Error::UnknownPackage(package.to_owned()))
+    Ok(None)
 }
 
-fn str_vec_to_str_hash(v: &Vec<String>) -> HashSet<String> {
-    let mut set = HashSet::new();
-    for i in v {
-        set.insert(i.clone());
+/// Every package and collection that directly requires `name`
+fn direct_dependents(name: &str, repo: &Repository) -> Vec<String> {
+    let packages = repo
+        .packages
+        .iter()
+        .filter(|p| p.dependencies.iter().any(|d| d == name))
+        .map(|p| p.name.clone());
+
+    let collections = repo
+        .collections
+        .iter()
+        .filter(|c| c.packages.iter().any(|p| p == name))
+        .map(|c| c.name.clone());
+
+    packages.chain(collections).collect()
+}
+
+/// Every package and collection that requires `name`, directly or through
+/// others, together with how many steps away from `name` it is
+pub fn reverse_dependencies(name: &str, repo: &Repository) -> Vec<(String, usize)> {
+    let mut queue = VecDeque::from([(name.to_owned(), 0)]);
+    let mut seen = HashSet::from([name.to_owned()]);
+    let mut result = vec![];
+
+    while let Some((current, depth)) = queue.pop_front() {
+        for d in direct_dependents(&current, repo) {
+            if seen.insert(d.clone()) {
+                result.push((d.clone(), depth + 1));
+                queue.push_back((d, depth + 1));
+            }
+        }
     }
 
-    set
+    result
 }
@@ -7,9 +7,15 @@ use crate::{
 };
 use mlua::{Function, IntoLua, Lua, Value};
 
+/// A package's `.dotman.lua`, every hook is optional
 pub struct Script {
+    pre_install: Option<mlua::Function>,
     post_install: Option<mlua::Function>,
+    pre_update: Option<mlua::Function>,
     post_update: Option<mlua::Function>,
+    pre_uninstall: Option<mlua::Function>,
+    post_uninstall: Option<mlua::Function>,
+    on_branch_switch: Option<mlua::Function>,
 
     cwd: String,
     lua: mlua::Lua,
@@ -29,12 +35,14 @@ impl Script {
 
         match module {
             Value::Table(t) => {
-                let post_install = t.get::<Function>("post_install")?;
-                let post_update = t.get::<Function>("post_update")?;
-
                 let script = Self {
-                    post_install: Some(post_install),
-                    post_update: Some(post_update),
+                    pre_install: t.get::<Option<Function>>("pre_install")?,
+                    post_install: t.get::<Option<Function>>("post_install")?,
+                    pre_update: t.get::<Option<Function>>("pre_update")?,
+                    post_update: t.get::<Option<Function>>("post_update")?,
+                    pre_uninstall: t.get::<Option<Function>>("pre_uninstall")?,
+                    post_uninstall: t.get::<Option<Function>>("post_uninstall")?,
+                    on_branch_switch: t.get::<Option<Function>>("on_branch_switch")?,
                     cwd: dir.to_string(),
                     lua,
                 };
@@ -180,6 +188,14 @@ impl Script {
         Ok(())
     }
 
+    pub fn run_preinstall(&self) -> DotManResult<()> {
+        if let Some(pre_install) = &self.pre_install {
+            pre_install.call::<Value>(())?;
+        }
+
+        Ok(())
+    }
+
     pub fn run_postinstall(&self) -> DotManResult<()> {
         if let Some(post_install) = &self.post_install {
             post_install.call::<Value>(())?;
@@ -188,6 +204,14 @@ impl Script {
         Ok(())
     }
 
+    pub fn run_preupdate(&self) -> DotManResult<()> {
+        if let Some(pre_update) = &self.pre_update {
+            pre_update.call::<Value>(())?;
+        }
+
+        Ok(())
+    }
+
     pub fn run_postupdate(&self) -> DotManResult<()> {
         if let Some(post_update) = &self.post_update {
             post_update.call::<Value>(())?;
@@ -195,4 +219,30 @@ impl Script {
 
         Ok(())
     }
+
+    pub fn run_preuninstall(&self) -> DotManResult<()> {
+        if let Some(pre_uninstall) = &self.pre_uninstall {
+            pre_uninstall.call::<Value>(())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn run_postuninstall(&self) -> DotManResult<()> {
+        if let Some(post_uninstall) = &self.post_uninstall {
+            post_uninstall.call::<Value>(())?;
+        }
+
+        Ok(())
+    }
+
+    /// Called with the branch that was checked out, `nil` for a detached HEAD, and the new branch
+    pub fn run_on_branch_switch(&self, from: &str, to: &str) -> DotManResult<()> {
+        if let Some(on_branch_switch) = &self.on_branch_switch {
+            let from = (!from.is_empty()).then_some(from);
+            on_branch_switch.call::<Value>((from, to))?;
+        }
+
+        Ok(())
+    }
 }
@@ -1,10 +1,25 @@
 use serde::Deserialize;
 
-use crate::remote::Remote;
+use crate::{errors::DotManResult, remote::Remote, utils};
 
-#[derive(Debug, Deserialize)]
+/// A package repository, when several define the same package or collection the
+/// one with the highest `priority` wins
+#[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Source {
+    pub name: String,
     pub remote: Remote,
     pub path: String,
+    #[serde(default)]
+    pub priority: i64,
+}
+
+impl Source {
+    pub fn url(&self) -> String {
+        self.remote.url()
+    }
+
+    pub fn path(&self) -> DotManResult<String> {
+        utils::expand("", &self.path)
+    }
 }
@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::DotManResult, utils};
+
+/// Everything dotman has installed on this machine, stored at
+/// `$XDG_STATE_HOME/dotman/state.toml`
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct State {
+    /// Collections that were installed, `why` starts from them
+    #[serde(default)]
+    pub collections: BTreeSet<String>,
+
+    #[serde(default)]
+    pub packages: BTreeMap<String, InstalledPackage>,
+
+    /// Directories moved away by `install --force`, oldest first
+    #[serde(default)]
+    pub backups: BTreeMap<String, Vec<Backup>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct InstalledPackage {
+    pub commit: String,
+    /// Seconds since the unix epoch
+    pub installed_at: u64,
+    /// Seconds since the unix epoch
+    pub updated_at: Option<u64>,
+    pub install_path: String,
+    /// Url of the package's own git repository
+    pub url: String,
+    /// Url of the source the package was found in
+    pub repository: String,
+    pub reason: InstallReason,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Backup {
+    pub path: String,
+    pub original_path: String,
+    /// Seconds since the unix epoch
+    pub created_at: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallReason {
+    Explicit,
+    Dependency,
+}
+
+impl State {
+    pub fn path() -> DotManResult<String> {
+        let base = utils::xdg_dir("XDG_STATE_HOME", "~/.local/state")?;
+
+        Ok(Path::new(&base)
+            .join("dotman")
+            .join("state.toml")
+            .to_string_lossy()
+            .to_string())
+    }
+
+    pub fn load() -> DotManResult<State> {
+        let content = match fs::read_to_string(Self::path()?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(e) => return Err(e.into()),
+            Ok(c) => c,
+        };
+
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> DotManResult<()> {
+        let path = Self::path()?;
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages.get(name)
+    }
+
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.packages.contains_key(name)
+    }
+
+    /// Records a fresh install or an update of `name`. A package that was once
+    /// installed explicitly stays explicit even if it's later pulled in as a dependency.
+    pub fn record(&mut self, name: &str, mut entry: InstalledPackage) {
+        if let Some(existing) = self.packages.get(name) {
+            entry.installed_at = existing.installed_at;
+            entry.updated_at = Some(now());
+
+            if existing.reason == InstallReason::Explicit {
+                entry.reason = InstallReason::Explicit;
+            }
+        }
+
+        self.packages.insert(name.to_owned(), entry);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<InstalledPackage> {
+        self.packages.remove(name)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
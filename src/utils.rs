@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{fs, io, path::Path};
 
 use mlua::{Lua, Value as LuaValue};
 use serde_json::Value as JSONValue;
@@ -26,6 +26,60 @@ pub fn from_cwd(cwd: &str, path: &str) -> DotManResult<String> {
     }
 }
 
+/// `$<var>` if it's set, `default` otherwise, as described by the XDG base directory spec
+pub fn xdg_dir(var: &str, default: &str) -> DotManResult<String> {
+    match std::env::var(var) {
+        Ok(dir) if !dir.is_empty() => Ok(dir),
+        _ => expand("", default),
+    }
+}
+
+/// Moves `from` to `to`, falling back to copying when they are on different filesystems.
+/// `to` must not exist yet
+pub fn move_dir(from: &Path, to: &Path) -> DotManResult<()> {
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", to.display()),
+        )
+        .into());
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_dir(from, to)?;
+            fs::remove_dir_all(from)?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> DotManResult<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, target)?;
+        } else if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn serde_json_value_to_mlua_value(lua: &Lua, json: JSONValue) -> DotManResult<LuaValue> {
     let lua_value = match json {
         JSONValue::Null => LuaValue::Nil,